@@ -1,25 +1,24 @@
 use std::{collections::HashMap, io};
-use tausch::{TauschError, VariableValue, eval};
+use tausch::{VariableValue, eval, render_error};
 
 pub fn main() {
     let mut vars = HashMap::<String, VariableValue>::new();
-    vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
-    vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
-    vars.insert("cond".to_string(), VariableValue::Bool(true));
-    vars.insert("ncond".to_string(), VariableValue::Bool(false));
+    vars.insert("hello".into(), "42".into());
+    vars.insert("world".into(), "69".into());
+    vars.insert("cond".into(), true.into());
+    vars.insert("ncond".into(), false.into());
 
     let mut buf = String::new();
     while io::stdin().read_line(&mut buf).is_ok() && !buf.contains("exit") {
-        match eval(vars.clone(), buf.clone()) {
+        match eval(&vars, &buf) {
             Ok(var) => match var {
                 VariableValue::Bool(val) => println!("result: value='{}' (bool)", val),
                 VariableValue::Str(val) => println!("result: value='{}' (str)", val),
+                VariableValue::Int(val) => println!("result: value='{}' (int)", val),
+                VariableValue::Float(val) => println!("result: value='{}' (float)", val),
                 VariableValue::Empty => println!("result: emptyness"),
             },
-            Err(e) => match e {
-                TauschError::Tokenizer(err) => println!("Tokenizing failed: {}", err),
-                TauschError::Parser(err) => println!("Parsing failed: {}", err),
-            },
+            Err(e) => println!("{}", render_error(&buf, &e)),
         }
         buf.clear();
     }
@@ -1,339 +1,7300 @@
-use core::fmt;
-use std::{any::Any, collections::HashMap};
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use iter_tools::Itertools;
+extern crate alloc;
 
-#[derive(Debug)]
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{cell::RefCell, fmt, iter::Peekable, str::CharIndices};
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum TauschError {
-    Tokenizer(String),
-    Parser(String),
+    /// `span` is `None` if the error is not caused by a part of the input.
+    Tokenizer {
+        message: String,
+        span: Option<Span>,
+    },
+    Parser(ParserError),
+}
+
+impl fmt::Display for TauschError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TauschError::Tokenizer { message, .. } => write!(f, "Tokenizing failed: {message}"),
+            TauschError::Parser(err) => write!(f, "Parsing failed: {err}"),
+        }
+    }
 }
 
+impl core::error::Error for TauschError {}
+
 #[derive(Clone, Debug, PartialEq)]
+pub enum ParserError {
+    /// `span` points at the first reference to the variable if the input is known.
+    UndefinedVariable {
+        name: String,
+        span: Option<Span>,
+    },
+    TypeMismatch {
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+        span: Option<Span>,
+    },
+    /// Like [`ParserError::TypeMismatch`], but for a literal or a computed value, e.g.
+    /// `true + 1` or `(a | len)[0]`.
+    OperandTypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// `expected_tokens` lists the token types that would have been valid instead, it is
+    /// empty if the parser expected something more complex than a single token.
+    UnexpectedToken {
+        expected: String,
+        expected_tokens: Vec<TokenType>,
+        found: String,
+        column: usize,
+        span: Span,
+    },
+    UnexpectedEof {
+        expected: String,
+        expected_tokens: Vec<TokenType>,
+    },
+    TrailingToken {
+        found: String,
+        column: usize,
+        span: Span,
+    },
+    InvalidLiteral(String),
+    DivisionByZero,
+    IntegerOverflow,
+    IndexOutOfRange {
+        index: i64,
+        len: usize,
+    },
+    NoTokens,
+    DuplicateElse {
+        column: usize,
+        span: Span,
+    },
+    NestingTooDeep {
+        max_depth: usize,
+        column: usize,
+        span: Span,
+    },
+    InStatement {
+        index: usize,
+        error: Box<ParserError>,
+    },
+    /// The function is not part of [`EvalOptions::functions`].
+    UnknownFunction {
+        name: String,
+    },
+    UnknownTransform {
+        name: String,
+        column: usize,
+        span: Span,
+    },
+    /// `chain` lists the variables from the first one that is part of the cycle back to itself.
+    RecursiveVariable {
+        chain: Vec<String>,
+    },
+}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParserError::UndefinedVariable { name, .. } => {
+                write!(f, "Variable '{name}' does not exist!")
+            }
+            ParserError::TypeMismatch { name, expected, .. } => {
+                write!(f, "Variable '{name}' is not a {expected}!")
+            }
+            ParserError::OperandTypeMismatch { expected, found } => {
+                write!(f, "Expected a value of type {expected}, found {found}!")
+            }
+            ParserError::UnexpectedToken {
+                expected,
+                found,
+                column,
+                ..
+            } => write!(f, "{expected} Found '{found}' (at column {column})"),
+            ParserError::UnexpectedEof { expected, .. } => {
+                write!(f, "{expected} Found end of input")
+            }
+            ParserError::TrailingToken { found, column, .. } => write!(
+                f,
+                "Unexpected token '{found}' after the end of the expression (at column {column})"
+            ),
+            ParserError::InvalidLiteral(label) => write!(f, "Invalid literal: '{label}'"),
+            ParserError::DivisionByZero => write!(f, "Division by zero"),
+            ParserError::IntegerOverflow => write!(f, "Integer overflow"),
+            ParserError::IndexOutOfRange { index, len } => {
+                write!(
+                    f,
+                    "Index {index} is out of range for a string of length {len}"
+                )
+            }
+            ParserError::NoTokens => write!(f, "No tokens"),
+            ParserError::DuplicateElse { column, .. } => write!(
+                f,
+                "Unexpected second else-branch for the same if-statement (at column {column})"
+            ),
+            ParserError::NestingTooDeep {
+                max_depth, column, ..
+            } => write!(
+                f,
+                "Nesting too deep, at most {max_depth} levels are allowed (at column {column})"
+            ),
+            ParserError::InStatement { index, error } => write!(f, "Statement {index}: {error}"),
+            ParserError::UnknownFunction { name } => write!(f, "Function '{name}' does not exist!"),
+            ParserError::UnknownTransform { name, column, .. } => write!(
+                f,
+                "Unknown transform '{name}', expected 'upper', 'lower', 'trim' or 'len' (at column {column})"
+            ),
+            ParserError::RecursiveVariable { chain } => {
+                write!(f, "Variable references itself: {}", chain.join(" -> "))
+            }
+        }
+    }
+}
+
+impl ParserError {
+    /// The part of the input that caused the error, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ParserError::UndefinedVariable { span, .. }
+            | ParserError::TypeMismatch { span, .. } => *span,
+            ParserError::UnexpectedToken { span, .. }
+            | ParserError::TrailingToken { span, .. }
+            | ParserError::DuplicateElse { span, .. }
+            | ParserError::NestingTooDeep { span, .. }
+            | ParserError::UnknownTransform { span, .. } => Some(*span),
+            ParserError::InStatement { error, .. } => error.span(),
+            _ => None,
+        }
+    }
+
+    // `find` returns the span of the first reference to a variable.
+    fn locate_variable(mut self, find: impl Fn(&str) -> Option<Span>) -> ParserError {
+        if let ParserError::UndefinedVariable { name, span }
+        | ParserError::TypeMismatch { name, span, .. } = &mut self
+            && span.is_none()
+        {
+            *span = find(name);
+        }
+        self
+    }
+}
+
+/// Values are totally ordered: `Empty` sorts first, followed by bools (`false < true`),
+/// numbers and strings. Ints and floats are compared numerically, a float sorts after an int of
+/// the same value. Floats use [`f64::total_cmp`], so a `NaN` equals itself. `==` inside of
+/// templates follows IEEE 754 instead and treats `1` and `1.0` as equal.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum VariableValue {
     Bool(bool),
     Str(String),
+    Int(i64),
+    Float(f64),
     Empty,
 }
 
-#[derive(Clone)]
+impl VariableValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            VariableValue::Bool(_) => "bool",
+            VariableValue::Str(_) => "str",
+            VariableValue::Int(_) => "int",
+            VariableValue::Float(_) => "float",
+            VariableValue::Empty => "empty",
+        }
+    }
+
+    /// `Empty`, `false`, empty strings and zero are falsy, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            VariableValue::Bool(val) => *val,
+            VariableValue::Str(val) => !val.is_empty(),
+            VariableValue::Int(val) => *val != 0,
+            VariableValue::Float(val) => *val != 0.0,
+            VariableValue::Empty => false,
+        }
+    }
+
+    /// `==` inside of templates: like [`PartialEq`], but numbers are compared like `<` compares
+    /// them. An int equals a float of the same value and floats follow IEEE 754, so `NaN` is
+    /// not equal to itself and `-0.0` equals `0.0`.
+    fn template_eq(&self, other: &VariableValue) -> bool {
+        match (self, other) {
+            (VariableValue::Float(lhs), VariableValue::Float(rhs)) => lhs == rhs,
+            (VariableValue::Int(lhs), VariableValue::Float(rhs)) => *lhs as f64 == *rhs,
+            (VariableValue::Float(lhs), VariableValue::Int(rhs)) => *lhs == *rhs as f64,
+            _ => self == other,
+        }
+    }
+
+    /// Whether the value is [`VariableValue::Empty`], an empty `Str` is not empty.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, VariableValue::Empty)
+    }
+
+    pub fn is_bool(&self) -> bool {
+        matches!(self, VariableValue::Bool(_))
+    }
+
+    pub fn is_str(&self) -> bool {
+        matches!(self, VariableValue::Str(_))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            VariableValue::Str(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            VariableValue::Bool(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            VariableValue::Empty => 0,
+            VariableValue::Bool(_) => 1,
+            VariableValue::Int(_) | VariableValue::Float(_) => 2,
+            VariableValue::Str(_) => 3,
+        }
+    }
+}
+
+/// Compares exactly, converting `int` to `f64` would round for large values.
+fn cmp_int_float(int: i64, float: f64) -> core::cmp::Ordering {
+    use core::cmp::Ordering::{Greater, Less};
+
+    if float.is_nan() {
+        return if float.is_sign_negative() {
+            Greater
+        } else {
+            Less
+        };
+    }
+    // 2^63 is exactly representable, every float at or above it is larger than any i64.
+    if float >= 9_223_372_036_854_775_808.0 {
+        return Less;
+    }
+    if float < -9_223_372_036_854_775_808.0 {
+        return Greater;
+    }
+    // In range, so the cast truncates towards zero and the result is exactly representable.
+    let trunc = float as i64;
+    int.cmp(&trunc).then(if float > trunc as f64 {
+        Less
+    } else if float < trunc as f64 {
+        Greater
+    } else {
+        // Same value, ints sort before floats.
+        Less
+    })
+}
+
+impl Ord for VariableValue {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        match (self, other) {
+            (VariableValue::Bool(lhs), VariableValue::Bool(rhs)) => lhs.cmp(rhs),
+            (VariableValue::Str(lhs), VariableValue::Str(rhs)) => lhs.cmp(rhs),
+            (VariableValue::Int(lhs), VariableValue::Int(rhs)) => lhs.cmp(rhs),
+            (VariableValue::Float(lhs), VariableValue::Float(rhs)) => lhs.total_cmp(rhs),
+            (VariableValue::Int(lhs), VariableValue::Float(rhs)) => cmp_int_float(*lhs, *rhs),
+            (VariableValue::Float(lhs), VariableValue::Int(rhs)) => {
+                cmp_int_float(*rhs, *lhs).reverse()
+            }
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for VariableValue {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for VariableValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for VariableValue {}
+
+/// Floats are hashed by their bit pattern, like [`f64::total_cmp`] they are only equal if
+/// their bits are: `NaN` equals itself, but `-0.0` and `0.0` are different values.
+impl core::hash::Hash for VariableValue {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        core::mem::discriminant(self).hash(state);
+        match self {
+            VariableValue::Bool(val) => val.hash(state),
+            VariableValue::Str(val) => val.hash(state),
+            VariableValue::Int(val) => val.hash(state),
+            VariableValue::Float(val) => val.to_bits().hash(state),
+            VariableValue::Empty => {}
+        }
+    }
+}
+
+/// ```
+/// use tausch::VariableValue;
+///
+/// assert_eq!(VariableValue::from("42"), VariableValue::Str("42".to_string()));
+/// assert_eq!(VariableValue::from("42".to_string()), VariableValue::Str("42".to_string()));
+/// assert_eq!(VariableValue::from(true), VariableValue::Bool(true));
+/// assert_eq!(VariableValue::from(42), VariableValue::Int(42));
+/// ```
+impl From<&str> for VariableValue {
+    fn from(val: &str) -> Self {
+        VariableValue::Str(val.to_string())
+    }
+}
+
+impl From<String> for VariableValue {
+    fn from(val: String) -> Self {
+        VariableValue::Str(val)
+    }
+}
+
+impl From<bool> for VariableValue {
+    fn from(val: bool) -> Self {
+        VariableValue::Bool(val)
+    }
+}
+
+impl From<i64> for VariableValue {
+    fn from(val: i64) -> Self {
+        VariableValue::Int(val)
+    }
+}
+
+impl From<f64> for VariableValue {
+    fn from(val: f64) -> Self {
+        VariableValue::Float(val)
+    }
+}
+
+/// Builds a `HashMap<String, VariableValue>`, converting keys and values with `From`.
+///
+/// ```
+/// let vars = tausch::vars! { "hello" => "42", "cond" => true };
+/// assert_eq!(tausch::eval(&vars, "if cond ; hello").unwrap().to_string(), "42");
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! vars {
+    ($($name:expr => $val:expr),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut vars = ::std::collections::HashMap::<String, $crate::VariableValue>::new();
+        $(vars.insert(::std::string::String::from($name), $crate::VariableValue::from($val));)*
+        vars
+    }};
+}
+
+impl fmt::Display for VariableValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VariableValue::Bool(val) => write!(f, "{val}"),
+            VariableValue::Str(val) => write!(f, "{val}"),
+            VariableValue::Int(val) => write!(f, "{val}"),
+            VariableValue::Float(val) => write!(f, "{val}"),
+            VariableValue::Empty => Ok(()),
+        }
+    }
+}
+
+/// Where the variables of an expression are looked up.
+pub trait VariableSource {
+    fn get(&self, name: &str) -> Option<VariableValue>;
+}
+
+#[cfg(feature = "std")]
+impl VariableSource for HashMap<String, VariableValue> {
+    fn get(&self, name: &str) -> Option<VariableValue> {
+        HashMap::get(self, name).cloned()
+    }
+}
+
+impl VariableSource for BTreeMap<String, VariableValue> {
+    fn get(&self, name: &str) -> Option<VariableValue> {
+        BTreeMap::get(self, name).cloned()
+    }
+}
+
+/// Computes variables on demand, e.g. `|name: &str| std::env::var(name).ok().map(Into::into)`.
+impl<F: Fn(&str) -> Option<VariableValue>> VariableSource for F {
+    fn get(&self, name: &str) -> Option<VariableValue> {
+        self(name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenType {
     Variable,
+    IntLiteral,
+    FloatLiteral,
+    StrLiteral,
+    /// `true` or `false`.
+    BoolLiteral,
     IfStart,
+    /// `unless`, an `if` with a negated condition.
+    UnlessStart,
     IfNegate,
     IfEnd,
     IfElse,
+    And,
+    Or,
+    Eq,
+    NotEq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    ParenOpen,
+    ParenClose,
+    Coalesce,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Pipe,
+    BracketOpen,
+    BracketClose,
+    In,
 }
 
 impl fmt::Display for TokenType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
             "{}",
             match self {
                 TokenType::Variable => "Variable",
+                TokenType::IntLiteral => "IntLiteral",
+                TokenType::FloatLiteral => "FloatLiteral",
+                TokenType::StrLiteral => "StrLiteral",
+                TokenType::BoolLiteral => "BoolLiteral",
                 TokenType::IfStart => "IfStart",
+                TokenType::UnlessStart => "UnlessStart",
                 TokenType::IfNegate => "IfNegate",
                 TokenType::IfEnd => "IfEnd",
                 TokenType::IfElse => "IfElse",
+                TokenType::And => "And",
+                TokenType::Or => "Or",
+                TokenType::Eq => "Eq",
+                TokenType::NotEq => "NotEq",
+                TokenType::Less => "Less",
+                TokenType::LessEq => "LessEq",
+                TokenType::Greater => "Greater",
+                TokenType::GreaterEq => "GreaterEq",
+                TokenType::ParenOpen => "ParenOpen",
+                TokenType::ParenClose => "ParenClose",
+                TokenType::Coalesce => "Coalesce",
+                TokenType::Plus => "Plus",
+                TokenType::Minus => "Minus",
+                TokenType::Star => "Star",
+                TokenType::Slash => "Slash",
+                TokenType::Pipe => "Pipe",
+                TokenType::BracketOpen => "BracketOpen",
+                TokenType::BracketClose => "BracketClose",
+                TokenType::In => "In",
             }
         )
     }
 }
 
-impl PartialEq for TokenType {
-    fn eq(&self, other: &Self) -> bool {
-        self.type_id() == other.type_id()
-    }
+/// Byte offsets into the input, `end` is exclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
 }
 
-#[derive(Clone)]
-pub struct Token {
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Token<'a> {
     pub typ: TokenType,
-    pub label: String,
+    /// Borrows from the tokenized input wherever possible.
+    pub label: Cow<'a, str>,
+    /// Byte offsets of the token inside of the tokenized input.
+    pub span: Span,
+    /// Column (starting at 1) of the first character of the token on its line.
+    pub column: usize,
+    /// Whitespace and comments in front of the token, only recorded if enabled with
+    /// [`Tokenizer::with_trivia`].
+    pub leading_ws: Cow<'a, str>,
+    /// Like `leading_ws`, but after the token. Only the last token of an input has any.
+    pub trailing_ws: Cow<'a, str>,
+    /// Source text of a variable whose name contains escapes like `\if`, only recorded if
+    /// enabled with [`Tokenizer::with_trivia`].
+    pub escaped_source: Option<Cow<'a, str>>,
 }
 
-impl PartialEq for Token {
-    fn eq(&self, other: &Self) -> bool {
-        self.label == other.label && self.typ.type_id() == other.typ.type_id()
+impl Token<'_> {
+    /// Creates a token that is not part of any input, e.g. a reserved token.
+    pub fn new(typ: TokenType, label: impl Into<String>) -> Self {
+        Token {
+            typ,
+            label: Cow::Owned(label.into()),
+            span: Span { start: 0, end: 0 },
+            column: 0,
+            leading_ws: Cow::Borrowed(""),
+            trailing_ws: Cow::Borrowed(""),
+            escaped_source: None,
+        }
     }
-}
-
-pub struct Tokenizer {
-    reserved_tokens: Vec<Token>,
-}
 
-impl Tokenizer {
-    pub fn new() -> Tokenizer {
-        let reserved_toks = vec![
-            Token {
-                typ: TokenType::IfStart,
-                label: "if".to_string(),
-            },
-            Token {
-                typ: TokenType::IfEnd,
-                label: ";".to_string(),
-            },
-            Token {
-                typ: TokenType::IfElse,
-                label: ":".to_string(),
-            },
-            Token {
-                typ: TokenType::IfNegate,
-                label: "!".to_string(),
-            },
-        ];
-        Tokenizer {
-            reserved_tokens: reserved_toks,
+    /// Copies borrowed parts of the token, so that it outlives the tokenized input.
+    pub fn into_owned(self) -> Token<'static> {
+        Token {
+            label: Cow::Owned(self.label.into_owned()),
+            leading_ws: Cow::Owned(self.leading_ws.into_owned()),
+            trailing_ws: Cow::Owned(self.trailing_ws.into_owned()),
+            escaped_source: self
+                .escaped_source
+                .map(|source| Cow::Owned(source.into_owned())),
+            ..self
         }
     }
+}
 
-    fn is_allowed_var_name(&self, c: char) -> bool {
-        c.is_alphanumeric() || c == '_'
+/// Renders e.g. `Variable("hello")`.
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({:?})", self.typ, self.label)
     }
+}
 
-    fn is_allowed_token(&self, c: char) -> bool {
-        self.is_allowed_var_name(c)
-            || self
-                .reserved_tokens
-                .iter()
-                .find(|tok| tok.label.contains(c))
-                .is_some()
+fn reserved_token(typ: TokenType, label: &'static str) -> Token<'static> {
+    Token {
+        typ,
+        label: Cow::Borrowed(label),
+        span: Span { start: 0, end: 0 },
+        column: 0,
+        leading_ws: Cow::Borrowed(""),
+        trailing_ws: Cow::Borrowed(""),
+        escaped_source: None,
     }
+}
 
-    pub fn tokenize(&self, input: String) -> Result<Vec<Token>, TauschError> {
-        let mut toks: Vec<Token> = Vec::new();
-
-        let mut temp_string = String::new();
-        let mut iter = input.chars().into_iter().multipeek();
-        while let Some(c) = iter.next() {
-            match c {
-                c if self.is_allowed_token(c) => {
-                    temp_string.clear();
-                    temp_string.push(c);
-                    while let Some(pek) = iter.peek()
-                        && self.is_allowed_token(*pek)
-                    {
-                        temp_string.push(*pek);
-                        iter.next();
-                    }
-
-                    if let Some(tok) = self
-                        .reserved_tokens
-                        .iter()
-                        .find(|tok| tok.label == temp_string)
-                    {
-                        toks.push(tok.clone());
-                    } else {
-                        toks.push(Token {
-                            typ: TokenType::Variable,
-                            label: temp_string.clone(),
-                        });
-                    }
+fn is_trivia(text: &str) -> bool {
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => {
+                if !chars.any(|c| c == '\n') {
+                    return true;
                 }
-                c if c.is_whitespace() => temp_string.clear(),
-                c => return Err(TauschError::Tokenizer(format!("Unknown token: '{c}'"))),
             }
+            c if c.is_whitespace() => {}
+            _ => return false,
         }
-        Ok(toks)
     }
+    true
 }
 
-fn expect_token(
-    iterator: &mut std::slice::Iter<Token>,
-    typ: TokenType,
-    on_fail: String,
-) -> Result<Token, TauschError> {
-    match iterator.next() {
-        Some(tok) => {
-            if tok.typ == typ {
-                Ok(tok.clone())
-            } else {
-                Err(TauschError::Parser(on_fail))
+/// Turns tokens created with [`Tokenizer::with_trivia`] back into their source text.
+pub fn tokens_to_string(tokens: &[Token]) -> String {
+    let mut source = String::new();
+    for tok in tokens.iter() {
+        source.push_str(&tok.leading_ws);
+        if let Some(escaped) = &tok.escaped_source {
+            source.push_str(escaped);
+        } else if tok.typ == TokenType::Variable {
+            for (i, c) in tok.label.chars().enumerate() {
+                let plain = if i == 0 {
+                    unicode_ident::is_xid_start(c) || c == '_'
+                } else {
+                    unicode_ident::is_xid_continue(c)
+                };
+                if !plain {
+                    source.push('\\');
+                }
+                source.push(c);
+            }
+        } else if tok.typ == TokenType::StrLiteral {
+            source.push('"');
+            for c in tok.label.chars() {
+                if matches!(c, '"' | '\\') {
+                    source.push('\\');
+                }
+                source.push(c);
             }
+            source.push('"');
+        } else {
+            source.push_str(&tok.label);
         }
-        None => Err(TauschError::Parser(on_fail)),
+        source.push_str(&tok.trailing_ws);
     }
+    source
 }
 
-fn parse_if(
-    variables: HashMap<String, VariableValue>,
-    iterator: &mut std::slice::Iter<Token>,
-) -> Result<VariableValue, TauschError> {
-    let tok_condition = expect_token(
-        iterator,
-        TokenType::Variable,
-        "Expected variable name after 'if'!".to_string(),
-    )?;
-
-    let Some(var_condition) = variables.get(&tok_condition.label) else {
-        return Err(TauschError::Parser(format!(
-            "Variable '{}' does not exist!",
-            tok_condition.label
-        )));
-    };
+/// Id of a string inside of a [`StringInterner`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(pub u32);
 
-    let VariableValue::Bool(val_condition) = var_condition else {
-        return Err(TauschError::Parser(format!(
-            "Variable '{}' is not a bool!",
-            tok_condition.label
-        )));
-    };
+/// Stores every distinct string once, e.g. the labels of variables referenced many times.
+#[derive(Clone, Debug, Default)]
+pub struct StringInterner {
+    // Both share the same allocation of every string.
+    ids: BTreeMap<Arc<str>, Symbol>,
+    strings: Vec<Arc<str>>,
+}
 
-    expect_token(
-        iterator,
-        TokenType::IfEnd,
-        "Expected ';' after variable name inside of 'if'!".to_string(),
-    )?;
+impl StringInterner {
+    pub fn new() -> StringInterner {
+        StringInterner::default()
+    }
 
-    let tok_on_true = expect_token(
-        iterator,
-        TokenType::Variable,
-        "Expected variable name inside of 'if'-branch of if-statement.".to_string(),
-    )?;
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(symbol) = self.ids.get(string) {
+            return *symbol;
+        }
+        let symbol = Symbol(u32::try_from(self.strings.len()).expect("too many strings"));
+        let string: Arc<str> = Arc::from(string);
+        self.ids.insert(Arc::clone(&string), symbol);
+        self.strings.push(string);
+        symbol
+    }
 
-    let Some(val_on_true) = variables.get(&tok_on_true.label) else {
-        return Err(TauschError::Parser(format!(
-            "Variable '{}' does not exist!",
-            tok_on_true.label
-        )));
-    };
+    /// Panics if `symbol` was not created by this interner.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
 
-    let mut peek_iter = iterator.peekable();
-    let Some(tok_else) = peek_iter.peek() else {
-        return Ok(if *val_condition {
-            val_on_true.clone()
-        } else {
-            VariableValue::Empty
-        });
-    };
+    /// Number of distinct strings.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
 
-    if tok_else.typ != TokenType::IfElse {
-        return Err(TauschError::Parser(
-            "Expected ':' to start an 'else'-branch for the if-statement".to_string(),
-        ));
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
     }
+}
 
-    let tok_on_else = expect_token(
-        iterator,
-        TokenType::Variable,
-        "Expected variable name inside of 'if'-branch of if-statement.".to_string(),
-    )?;
+/// Like [`Token`], but the label is a [`Symbol`] of a [`StringInterner`] and no trivia is kept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InternedToken {
+    pub typ: TokenType,
+    pub label: Symbol,
+    pub span: Span,
+    pub column: usize,
+}
 
-    let Some(val_on_else) = variables.get(&tok_on_else.label) else {
-        return Err(TauschError::Parser(format!(
-            "Variable '{}' does not exist!",
-            tok_on_else.label
-        )));
-    };
+fn column_at(input: &str, offset: usize) -> usize {
+    let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+    input[line_start..offset].chars().count() + 1
+}
 
-    Ok(if *val_condition {
-        val_on_true.clone()
-    } else {
-        val_on_else.clone()
-    })
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TokenStats {
+    pub counts: BTreeMap<TokenType, usize>,
+    pub total: usize,
 }
 
-pub fn eval(
-    variables: HashMap<String, VariableValue>,
-    input: String,
-) -> Result<VariableValue, TauschError> {
-    let toker = Tokenizer::new();
-    let tokens = toker.tokenize(input)?;
+impl TokenStats {
+    pub fn count(&self, typ: TokenType) -> usize {
+        self.counts.get(&typ).copied().unwrap_or(0)
+    }
+}
 
-    let mut iter = tokens.iter();
-    match iter.next() {
-        Some(tok) => match tok.typ {
-            TokenType::Variable => {
-                if let Some(var) = variables.get(&tok.label) {
-                    return Ok(var.clone());
-                } else {
-                    return Err(TauschError::Parser(format!(
-                        "Variable '{}' not found!",
-                        tok.label
-                    )));
-                }
-            }
-            TokenType::IfStart => return parse_if(variables, &mut iter),
-            _ => {
-                return Err(TauschError::Parser(
-                    "Expected start of an if-statement or variable name!".to_string(),
-                ));
-            }
-        },
-        None => return Err(TauschError::Parser("No tokens".to_string())),
+pub struct Tokenizer {
+    reserved_tokens: Vec<Token<'static>>,
+    /// Every character that appears in a reserved label but is not allowed in variable names.
+    symbol_chars: BTreeSet<char>,
+    record_trivia: bool,
+    ignore_keyword_case: bool,
+    max_identifier_len: usize,
+    max_tokens: Option<usize>,
+    lenient: bool,
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
+impl Tokenizer {
+    pub fn new() -> Tokenizer {
+        let reserved_toks = vec![
+            reserved_token(TokenType::IfStart, "if"),
+            reserved_token(TokenType::UnlessStart, "unless"),
+            reserved_token(TokenType::IfEnd, ";"),
+            reserved_token(TokenType::IfElse, ":"),
+            reserved_token(TokenType::IfNegate, "!"),
+            reserved_token(TokenType::BoolLiteral, "true"),
+            reserved_token(TokenType::BoolLiteral, "false"),
+            reserved_token(TokenType::And, "&&"),
+            reserved_token(TokenType::Or, "||"),
+            reserved_token(TokenType::Eq, "=="),
+            reserved_token(TokenType::NotEq, "!="),
+            reserved_token(TokenType::Less, "<"),
+            reserved_token(TokenType::LessEq, "<="),
+            reserved_token(TokenType::Greater, ">"),
+            reserved_token(TokenType::GreaterEq, ">="),
+            reserved_token(TokenType::ParenOpen, "("),
+            reserved_token(TokenType::ParenClose, ")"),
+            reserved_token(TokenType::Coalesce, "??"),
+            reserved_token(TokenType::Plus, "+"),
+            reserved_token(TokenType::Minus, "-"),
+            reserved_token(TokenType::Star, "*"),
+            reserved_token(TokenType::Slash, "/"),
+            reserved_token(TokenType::Pipe, "|"),
+            reserved_token(TokenType::BracketOpen, "["),
+            reserved_token(TokenType::BracketClose, "]"),
+            reserved_token(TokenType::In, "in"),
+        ];
+        Tokenizer::from_tokens(reserved_toks)
+    }
 
-    use crate::{VariableValue, eval};
+    /// Creates a tokenizer that uses `tokens` as its reserved keywords and symbols.
+    ///
+    /// Fails if there is no token for [`TokenType::IfStart`], [`TokenType::IfEnd`] or
+    /// [`TokenType::IfElse`].
+    pub fn with_tokens(tokens: Vec<Token<'static>>) -> Result<Tokenizer, TauschError> {
+        for typ in [TokenType::IfStart, TokenType::IfEnd, TokenType::IfElse] {
+            if !tokens.iter().any(|tok| tok.typ == typ) {
+                return Err(TauschError::Tokenizer {
+                    message: format!("Missing reserved token of type {typ}"),
+                    span: None,
+                });
+            }
+        }
+        Ok(Tokenizer::from_tokens(tokens))
+    }
 
-    #[test]
-    fn eval_var() {
-        let mut vars = HashMap::<String, VariableValue>::new();
-        let var = VariableValue::Str("42".to_string());
-        let var_str = "hello".to_string();
-        vars.insert(var_str.clone(), var.clone());
+    fn from_tokens(tokens: Vec<Token<'static>>) -> Tokenizer {
+        let mut tokenizer = Tokenizer {
+            reserved_tokens: tokens,
+            symbol_chars: BTreeSet::new(),
+            record_trivia: false,
+            ignore_keyword_case: false,
+            max_identifier_len: 1024,
+            max_tokens: None,
+            lenient: false,
+        };
+        tokenizer.symbol_chars = tokenizer
+            .reserved_tokens
+            .iter()
+            .flat_map(|tok| tok.label.chars())
+            .filter(|c| !tokenizer.is_allowed_var_name(*c))
+            .collect();
+        tokenizer
+    }
 
-        assert_eq!(eval(vars.clone(), var_str).expect("should never fail"), var);
+    /// Variable names and keywords follow the Unicode identifier rules (UAX #31): they start
+    /// with an `XID_Start` character or `_` and continue with `XID_Continue` characters.
+    fn is_var_name_start(&self, c: char) -> bool {
+        unicode_ident::is_xid_start(c) || c == '_'
     }
 
-    #[test]
-    fn eval_if_1() {
-        let mut vars = HashMap::<String, VariableValue>::new();
-        let var = VariableValue::Str("42".to_string());
-        vars.insert("hello".to_string(), var.clone());
-        vars.insert("cond".to_string(), VariableValue::Bool(true));
+    fn is_allowed_var_name(&self, c: char) -> bool {
+        unicode_ident::is_xid_continue(c)
+    }
 
-        assert_eq!(
-            eval(vars.clone(), "if cond ; hello".to_string()).expect("should never fail"),
-            var
-        );
+    /// A token may start with `c` if it can begin a variable name or keyword, or if `c` is
+    /// part of a reserved symbol. Whether a whole symbol matches is decided by `match_symbol`.
+    fn is_allowed_token(&self, c: char) -> bool {
+        self.is_var_name_start(c) || self.symbol_chars.contains(&c)
     }
 
-    #[test]
-    fn eval_if_2() {
-        let mut vars = HashMap::<String, VariableValue>::new();
-        let var = VariableValue::Str("42".to_string());
-        vars.insert("hello".to_string(), var.clone());
-        vars.insert("cond".to_string(), VariableValue::Bool(false));
+    /// Keywords and symbols with a meaning of their own, e.g. for syntax highlighting.
+    pub fn reserved_tokens(&self) -> &[Token<'static>] {
+        &self.reserved_tokens
+    }
 
-        assert_eq!(
-            eval(vars.clone(), "if cond ; hello".to_string()).expect("should never fail"),
-            VariableValue::Empty
-        );
+    fn match_symbol(&self, rest: &str) -> Option<&Token<'static>> {
+        // Maximal munch: prefer '!=' over '!' if both match.
+        self.reserved_tokens
+            .iter()
+            .filter(|tok| {
+                tok.label
+                    .chars()
+                    .next()
+                    .is_some_and(|c| !self.is_allowed_var_name(c))
+                    && rest.starts_with(tok.label.as_ref())
+            })
+            .max_by_key(|tok| tok.label.len())
     }
 
-    #[test]
-    fn eval_if_else_1() {
-        let mut vars = HashMap::<String, VariableValue>::new();
-        let var_hello = VariableValue::Str("42".to_string());
-        vars.insert("hello".to_string(), var_hello.clone());
-        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
-        vars.insert("cond".to_string(), VariableValue::Bool(true));
-        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+    /// Records the whitespace and comments around tokens, so that [`tokens_to_string`] can
+    /// reproduce the input exactly.
+    pub fn with_trivia(mut self, record: bool) -> Tokenizer {
+        self.record_trivia = record;
+        self
+    }
 
-        assert_eq!(
-            eval(vars.clone(), "if cond ; hello : world".to_string()).expect("should never fail"),
-            var_hello
-        );
+    /// Matches keywords like `if` regardless of their case, so `IF` and `If` are keywords too.
+    pub fn with_case_insensitive_keywords(mut self, ignore_case: bool) -> Tokenizer {
+        self.ignore_keyword_case = ignore_case;
+        self
     }
 
-    #[test]
+    /// Skips unknown characters instead of failing, see [`Tokenizer::tokenize_with_diagnostics`].
+    pub fn with_lenient(mut self, lenient: bool) -> Tokenizer {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Accepts `label` in place of the `;` after a condition, e.g. `then` for `if cond then a`.
+    ///
+    /// `;` keeps working and nothing else becomes optional. A keyword like `then` is reserved,
+    /// so it can no longer be used as a variable name without escaping it.
+    pub fn with_if_end_synonym(mut self, label: &'static str) -> Tokenizer {
+        let tok = reserved_token(TokenType::IfEnd, label);
+        let symbol_chars: Vec<char> = tok
+            .label
+            .chars()
+            .filter(|c| !self.is_allowed_var_name(*c))
+            .collect();
+        self.symbol_chars.extend(symbol_chars);
+        self.reserved_tokens.push(tok);
+        self
+    }
+
+    /// Rejects variable names and keywords longer than `max_len` characters, 1024 by default.
+    pub fn with_max_identifier_len(mut self, max_len: usize) -> Tokenizer {
+        self.max_identifier_len = max_len;
+        self
+    }
+
+    /// Fails as soon as the input has more than `max_tokens` tokens, there is no limit by
+    /// default.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Tokenizer {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Keywords and symbols share the `'static` label of their reserved token, only the other
+    /// labels are copied out of `input`.
+    pub fn tokenize(&self, input: String) -> Result<Vec<Token<'static>>, TauschError> {
+        Ok(self
+            .tokenize_borrowed(&input)?
+            .into_iter()
+            .map(|tok| match self.static_label(&tok) {
+                Some(label) => Token {
+                    label: Cow::Borrowed(label),
+                    ..tok.into_owned()
+                },
+                None => tok.into_owned(),
+            })
+            .collect())
+    }
+
+    fn static_label(&self, tok: &Token) -> Option<&'static str> {
+        if is_value_token(tok) && tok.typ != TokenType::BoolLiteral {
+            return None;
+        }
+        let reserved = self
+            .reserved_tokens
+            .iter()
+            .find(|reserved| reserved.typ == tok.typ && reserved.label == tok.label)?;
+        match reserved.label {
+            Cow::Borrowed(label) => Some(label),
+            Cow::Owned(_) => None,
+        }
+    }
+
+    /// Like [`Tokenizer::tokenize`], but the labels of the tokens borrow from `input`.
+    ///
+    /// Only string literals and variable names containing escape sequences allocate a label of
+    /// their own.
+    pub fn tokenize_borrowed<'a>(&self, input: &'a str) -> Result<Vec<Token<'a>>, TauschError> {
+        self.token_iter(input).collect()
+    }
+
+    /// Like [`Tokenizer::tokenize_borrowed`], but also returns a diagnostic for every unknown
+    /// character that was skipped because the tokenizer is lenient.
+    pub fn tokenize_with_diagnostics<'a>(
+        &self,
+        input: &'a str,
+    ) -> Result<(Vec<Token<'a>>, Vec<Diagnostic>), TauschError> {
+        let mut iter = self.token_iter(input);
+        let tokens = iter.by_ref().collect::<Result<Vec<_>, _>>()?;
+        Ok((tokens, iter.diagnostics))
+    }
+
+    /// Like [`Tokenizer::tokenize_borrowed`], but also counts the tokens per [`TokenType`].
+    pub fn tokenize_with_stats<'a>(
+        &self,
+        input: &'a str,
+    ) -> Result<(Vec<Token<'a>>, TokenStats), TauschError> {
+        let tokens = self.tokenize_borrowed(input)?;
+        let mut stats = TokenStats::default();
+        for tok in &tokens {
+            *stats.counts.entry(tok.typ).or_default() += 1;
+        }
+        stats.total = tokens.len();
+        Ok((tokens, stats))
+    }
+
+    /// Like [`Tokenizer::tokenize_borrowed`], but stores the labels in `interner`.
+    pub fn tokenize_interned(
+        &self,
+        input: &str,
+        interner: &mut StringInterner,
+    ) -> Result<Vec<InternedToken>, TauschError> {
+        self.token_iter(input)
+            .map(|tok| {
+                let tok = tok?;
+                Ok(InternedToken {
+                    typ: tok.typ,
+                    label: interner.intern(&tok.label),
+                    span: tok.span,
+                    column: tok.column,
+                })
+            })
+            .collect()
+    }
+
+    /// Lazily yields the tokens of `input`, e.g. for syntax highlighting.
+    ///
+    /// The iterator stops after yielding the first error.
+    pub fn token_iter<'a>(&self, input: &'a str) -> TokenIter<'_, 'a> {
+        self.token_iter_at(input, 0)
+    }
+
+    // Like `token_iter`, but skips the input before the byte offset `start`. Spans and
+    // columns stay relative to the whole input.
+    fn token_iter_at<'a>(&self, input: &'a str, start: usize) -> TokenIter<'_, 'a> {
+        let mut chars = input.char_indices();
+        while chars.offset() < start {
+            chars.next();
+        }
+        TokenIter {
+            tokenizer: self,
+            input,
+            iter: MultiPeek::new(chars),
+            column_chars: input.char_indices().peekable(),
+            column: 1,
+            prev_end: start,
+            after_value: false,
+            diagnostics: Vec::new(),
+            count: 0,
+            failed: false,
+        }
+    }
+}
+
+/// Lookahead over the characters of the input: [`MultiPeek::peek`] yields the characters
+/// after the last one returned by `next` one by one, until `next` or
+/// [`MultiPeek::reset_peek`] is called.
+struct MultiPeek<'a> {
+    iter: CharIndices<'a>,
+    cursor: CharIndices<'a>,
+    peeked: Option<(usize, char)>,
+}
+
+impl<'a> MultiPeek<'a> {
+    fn new(iter: CharIndices<'a>) -> MultiPeek<'a> {
+        MultiPeek {
+            cursor: iter.clone(),
+            iter,
+            peeked: None,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&(usize, char)> {
+        self.peeked = self.cursor.next();
+        self.peeked.as_ref()
+    }
+
+    fn reset_peek(&mut self) {
+        self.cursor = self.iter.clone();
+    }
+}
+
+impl Iterator for MultiPeek<'_> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        let next = self.iter.next();
+        self.cursor = self.iter.clone();
+        next
+    }
+}
+
+/// Iterator over the tokens of an input, created by [`Tokenizer::token_iter`].
+pub struct TokenIter<'t, 'a> {
+    tokenizer: &'t Tokenizer,
+    input: &'a str,
+    iter: MultiPeek<'a>,
+    column_chars: Peekable<CharIndices<'a>>,
+    column: usize,
+    prev_end: usize,
+    after_value: bool,
+    diagnostics: Vec<Diagnostic>,
+    count: usize,
+    failed: bool,
+}
+
+impl<'a> TokenIter<'_, 'a> {
+    fn next_token(&mut self) -> Result<Option<Token<'a>>, TauschError> {
+        let tokenizer = self.tokenizer;
+        let input = self.input;
+        let after_value = self.after_value;
+        let iter = &mut self.iter;
+        let make_token = |typ: TokenType, start: usize, end: usize| Token {
+            typ,
+            label: Cow::Borrowed(&input[start..end]),
+            span: Span { start, end },
+            column: 0,
+            leading_ws: Cow::Borrowed(""),
+            trailing_ws: Cow::Borrowed(""),
+            escaped_source: None,
+        };
+
+        while let Some((start, c)) = iter.next() {
+            match c {
+                '#' => {
+                    for (_, c) in iter.by_ref() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                '"' => {
+                    let content_start = start + 1;
+                    let mut escaped: Option<String> = None;
+                    let mut end = None;
+                    while let Some((offset, c)) = iter.next() {
+                        match c {
+                            '"' => {
+                                end = Some(offset);
+                                break;
+                            }
+                            '\\' => match iter.next() {
+                                Some((_, c @ ('"' | '\\'))) => escaped
+                                    .get_or_insert_with(|| input[content_start..offset].to_string())
+                                    .push(c),
+                                Some((offset, c)) => {
+                                    return Err(TauschError::Tokenizer {
+                                        message: format!(
+                                            "Unknown escape sequence '\\{c}' at column {}",
+                                            column_at(input, offset - 1)
+                                        ),
+                                        span: Some(Span {
+                                            start: offset - 1,
+                                            end: offset + c.len_utf8(),
+                                        }),
+                                    });
+                                }
+                                None => break,
+                            },
+                            c => {
+                                if let Some(escaped) = escaped.as_mut() {
+                                    escaped.push(c);
+                                }
+                            }
+                        }
+                    }
+
+                    let Some(end) = end else {
+                        return Err(TauschError::Tokenizer {
+                            message: format!(
+                                "Unterminated string literal starting at column {}",
+                                column_at(input, start)
+                            ),
+                            span: Some(Span {
+                                start,
+                                end: start + 1,
+                            }),
+                        });
+                    };
+                    return Ok(Some(Token {
+                        typ: TokenType::StrLiteral,
+                        label: match escaped {
+                            Some(escaped) => Cow::Owned(escaped),
+                            None => Cow::Borrowed(&input[content_start..end]),
+                        },
+                        span: Span {
+                            start,
+                            end: end + 1,
+                        },
+                        column: 0,
+                        leading_ws: Cow::Borrowed(""),
+                        trailing_ws: Cow::Borrowed(""),
+                        escaped_source: None,
+                    }));
+                }
+                // After a value, 'a -1' is a subtraction and not 'a' followed by '-1'.
+                c if c.is_ascii_digit()
+                    || (c == '-'
+                        && !after_value
+                        && iter.peek().is_some_and(|(_, pek)| pek.is_ascii_digit())) =>
+                {
+                    let mut end = start + c.len_utf8();
+                    let mut is_float = false;
+                    loop {
+                        iter.reset_peek();
+                        while let Some((offset, pek)) = iter.peek()
+                            && tokenizer.is_allowed_var_name(*pek)
+                        {
+                            end = offset + pek.len_utf8();
+                            iter.next();
+                        }
+
+                        // A '.' followed by a digit continues the literal as a float.
+                        iter.reset_peek();
+                        if !is_float
+                            && iter.peek().is_some_and(|(_, pek)| *pek == '.')
+                            && iter.peek().is_some_and(|(_, pek)| pek.is_ascii_digit())
+                        {
+                            iter.next();
+                            end += 1;
+                            is_float = true;
+                            continue;
+                        }
+                        break;
+                    }
+
+                    let literal = &input[start..end];
+                    let digits = literal.strip_prefix('-').unwrap_or(literal);
+                    if !digits
+                        .chars()
+                        .all(|c| c.is_ascii_digit() || (is_float && c == '.'))
+                    {
+                        return Err(TauschError::Tokenizer {
+                            message: format!(
+                                "Invalid number literal: '{literal}' at column {}",
+                                column_at(input, start)
+                            ),
+                            span: Some(Span { start, end }),
+                        });
+                    }
+                    if is_float {
+                        return Ok(Some(make_token(TokenType::FloatLiteral, start, end)));
+                    }
+                    if literal.parse::<i64>().is_err() {
+                        return Err(TauschError::Tokenizer {
+                            message: format!(
+                                "Integer literal '{literal}' at column {} does not fit into 64 bits",
+                                column_at(input, start)
+                            ),
+                            span: Some(Span { start, end }),
+                        });
+                    }
+
+                    return Ok(Some(make_token(TokenType::IntLiteral, start, end)));
+                }
+                c if c == '\\' || tokenizer.is_allowed_token(c) => {
+                    if c != '\\' && !tokenizer.is_var_name_start(c) {
+                        let Some(tok) = tokenizer.match_symbol(&input[start..]) else {
+                            let message = format!(
+                                "Unknown token: '{c}' at column {}",
+                                column_at(input, start)
+                            );
+                            if tokenizer.lenient {
+                                self.diagnostics.push(Diagnostic {
+                                    message,
+                                    span: Some(Span {
+                                        start,
+                                        end: start + c.len_utf8(),
+                                    }),
+                                });
+                                continue;
+                            }
+                            return Err(TauschError::Tokenizer {
+                                message,
+                                span: Some(Span {
+                                    start,
+                                    end: start + c.len_utf8(),
+                                }),
+                            });
+                        };
+                        for _ in 1..tok.label.chars().count() {
+                            iter.next();
+                        }
+                        return Ok(Some(make_token(tok.typ, start, start + tok.label.len())));
+                    }
+
+                    // '\\' takes the next character literally, e.g. 'a\\:b' is the variable 'a:b'.
+                    let mut escaped: Option<String> = None;
+                    let mut end = start;
+                    let mut next = Some((start, c));
+                    let mut len = 0;
+                    while let Some((offset, c)) = next {
+                        len += 1;
+                        if len > tokenizer.max_identifier_len {
+                            return Err(TauschError::Tokenizer {
+                                message: format!(
+                                    "Identifier longer than {} characters at column {}",
+                                    tokenizer.max_identifier_len,
+                                    column_at(input, start)
+                                ),
+                                span: Some(Span {
+                                    start,
+                                    end: offset + c.len_utf8(),
+                                }),
+                            });
+                        }
+                        if c == '\\' {
+                            let Some((escaped_offset, c)) = iter.next() else {
+                                return Err(TauschError::Tokenizer {
+                                    message: format!(
+                                        "Dangling '\\' at column {}",
+                                        column_at(input, offset)
+                                    ),
+                                    span: Some(Span {
+                                        start: offset,
+                                        end: offset + 1,
+                                    }),
+                                });
+                            };
+                            escaped
+                                .get_or_insert_with(|| input[start..offset].to_string())
+                                .push(c);
+                            end = escaped_offset + c.len_utf8();
+                        } else {
+                            if let Some(escaped) = escaped.as_mut() {
+                                escaped.push(c);
+                            }
+                            end = offset + c.len_utf8();
+                        }
+
+                        iter.reset_peek();
+                        next = iter
+                            .peek()
+                            .copied()
+                            .filter(|(_, pek)| *pek == '\\' || tokenizer.is_allowed_var_name(*pek));
+                        if next.is_some() {
+                            iter.next();
+                        }
+                    }
+
+                    if let Some(escaped) = escaped {
+                        return Ok(Some(Token {
+                            label: Cow::Owned(escaped),
+                            ..make_token(TokenType::Variable, start, end)
+                        }));
+                    }
+
+                    let word = &input[start..end];
+                    let lowercase;
+                    let candidate = if tokenizer.ignore_keyword_case {
+                        lowercase = word.to_lowercase();
+                        lowercase.as_str()
+                    } else {
+                        word
+                    };
+                    let typ = match tokenizer.reserved_tokens.iter().find(|tok| {
+                        if tokenizer.ignore_keyword_case {
+                            tok.label.to_lowercase() == candidate
+                        } else {
+                            tok.label == candidate
+                        }
+                    }) {
+                        Some(tok) => tok.typ,
+                        None => TokenType::Variable,
+                    };
+                    return Ok(Some(make_token(typ, start, end)));
+                }
+                c if c.is_whitespace() => {}
+                c => {
+                    let message =
+                        format!("Unknown token: '{c}' at column {}", column_at(input, start));
+                    if tokenizer.lenient {
+                        self.diagnostics.push(Diagnostic {
+                            message,
+                            span: Some(Span {
+                                start,
+                                end: start + c.len_utf8(),
+                            }),
+                        });
+                        continue;
+                    }
+                    return Err(TauschError::Tokenizer {
+                        message,
+                        span: Some(Span {
+                            start,
+                            end: start + c.len_utf8(),
+                        }),
+                    });
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for TokenIter<'_, 'a> {
+    type Item = Result<Token<'a>, TauschError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.next_token() {
+            Ok(Some(tok))
+                if self
+                    .tokenizer
+                    .max_tokens
+                    .is_some_and(|max| self.count >= max) =>
+            {
+                self.failed = true;
+                Some(Err(TauschError::Tokenizer {
+                    message: format!("Too many tokens, at most {} are allowed", self.count),
+                    span: Some(tok.span),
+                }))
+            }
+            Ok(Some(mut tok)) => {
+                self.count += 1;
+                // Calling column_at for every token would be quadratic on long lines.
+                while let Some((_, c)) = self
+                    .column_chars
+                    .next_if(|(offset, _)| *offset < tok.span.start)
+                {
+                    self.column = if c == '\n' { 1 } else { self.column + 1 };
+                }
+                tok.column = self.column;
+
+                if self.tokenizer.record_trivia {
+                    tok.leading_ws = Cow::Borrowed(&self.input[self.prev_end..tok.span.start]);
+                    let rest = &self.input[tok.span.end..];
+                    if is_trivia(rest) {
+                        tok.trailing_ws = Cow::Borrowed(rest);
+                    }
+                    let source = &self.input[tok.span.start..tok.span.end];
+                    if tok.typ == TokenType::Variable && tok.label != source {
+                        tok.escaped_source = Some(Cow::Borrowed(source));
+                    }
+                }
+                self.prev_end = tok.span.end;
+                self.after_value = is_value_token(&tok)
+                    || matches!(tok.typ, TokenType::ParenClose | TokenType::BracketClose);
+                Some(Ok(tok))
+            }
+            Ok(None) => None,
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Tokenizes `input` using the default [`Tokenizer`].
+///
+/// ```
+/// use tausch::{TokenType, tokenize};
+///
+/// let tokens = tokenize("if cond ; hello").unwrap();
+/// assert_eq!(tokens.len(), 4);
+/// assert_eq!(tokens[0].typ, TokenType::IfStart);
+/// assert_eq!(tokens[1].label, "cond");
+/// ```
+pub fn tokenize(input: &str) -> Result<Vec<Token<'_>>, TauschError> {
+    Tokenizer::new().tokenize_borrowed(input)
+}
+
+fn expect_token<'a>(
+    iterator: &mut core::slice::Iter<'_, Token<'a>>,
+    typ: TokenType,
+    on_fail: String,
+) -> Result<Token<'a>, TauschError> {
+    match iterator.next() {
+        Some(tok) => {
+            if tok.typ == typ {
+                Ok(tok.clone())
+            } else {
+                Err(TauschError::Parser(ParserError::UnexpectedToken {
+                    expected: on_fail,
+                    expected_tokens: vec![typ],
+                    found: tok.label.to_string(),
+                    column: tok.column,
+                    span: tok.span,
+                }))
+            }
+        }
+        None => Err(TauschError::Parser(ParserError::UnexpectedEof {
+            expected: on_fail,
+            expected_tokens: vec![typ],
+        })),
+    }
+}
+
+fn accept_token<'a>(
+    iterator: &mut core::slice::Iter<'_, Token<'a>>,
+    typ: TokenType,
+) -> Option<Token<'a>> {
+    match iterator.as_slice().first() {
+        Some(tok) if tok.typ == typ => iterator.next().cloned(),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Condition {
+    Literal(bool),
+    Var(String),
+    /// `defined(name)`, true if the variable exists regardless of its type.
+    Defined(String),
+    Not(Box<Condition>),
+    /// The operands are variables, literals or arithmetic on numbers.
+    Eq(Box<Ast>, Box<Ast>),
+    /// `needle in haystack`, substring containment of two `Str` values.
+    Contains(Box<Ast>, Box<Ast>),
+    /// Numeric comparison, the operands are variables or number literals.
+    Order(Box<Ast>, Ordering, Box<Ast>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Ordering {
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl ArithOp {
+    fn precedence(self) -> u8 {
+        match self {
+            ArithOp::Add | ArithOp::Sub => 0,
+            ArithOp::Mul | ArithOp::Div => 1,
+        }
+    }
+
+    fn apply(self, lhs: VariableValue, rhs: VariableValue) -> Result<VariableValue, TauschError> {
+        let (lhs, rhs) = match (lhs, rhs) {
+            (VariableValue::Int(lhs), VariableValue::Int(rhs)) => {
+                let val = match self {
+                    ArithOp::Add => lhs.checked_add(rhs),
+                    ArithOp::Sub => lhs.checked_sub(rhs),
+                    ArithOp::Mul => lhs.checked_mul(rhs),
+                    ArithOp::Div if rhs == 0 => {
+                        return Err(TauschError::Parser(ParserError::DivisionByZero));
+                    }
+                    ArithOp::Div => lhs.checked_div(rhs),
+                };
+                return val
+                    .map(VariableValue::Int)
+                    .ok_or(TauschError::Parser(ParserError::IntegerOverflow));
+            }
+            (VariableValue::Int(lhs), VariableValue::Float(rhs)) => (lhs as f64, rhs),
+            (VariableValue::Float(lhs), VariableValue::Int(rhs)) => (lhs, rhs as f64),
+            (VariableValue::Float(lhs), VariableValue::Float(rhs)) => (lhs, rhs),
+            _ => unreachable!("eval_number only returns numbers"),
+        };
+        Ok(VariableValue::Float(match self {
+            ArithOp::Add => lhs + rhs,
+            ArithOp::Sub => lhs - rhs,
+            ArithOp::Mul => lhs * rhs,
+            ArithOp::Div if rhs == 0.0 => {
+                return Err(TauschError::Parser(ParserError::DivisionByZero));
+            }
+            ArithOp::Div => lhs / rhs,
+        }))
+    }
+}
+
+type Function = dyn Fn(&[VariableValue]) -> Result<VariableValue, TauschError> + Send + Sync;
+
+/// Functions that can be called from expressions, see [`EvalOptions::functions`].
+#[derive(Clone, Default)]
+pub struct FunctionRegistry {
+    functions: BTreeMap<String, Arc<Function>>,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> FunctionRegistry {
+        FunctionRegistry::default()
+    }
+
+    /// Registers `function` as `name`, replacing a previously registered function.
+    pub fn with_function(
+        mut self,
+        name: impl Into<String>,
+        function: impl Fn(&[VariableValue]) -> Result<VariableValue, TauschError>
+        + Send
+        + Sync
+        + 'static,
+    ) -> FunctionRegistry {
+        self.functions.insert(name.into(), Arc::new(function));
+        self
+    }
+
+    fn call(&self, name: &str, args: &[VariableValue]) -> Result<VariableValue, TauschError> {
+        match self.functions.get(name) {
+            Some(function) => function(args),
+            None => Err(TauschError::Parser(ParserError::UnknownFunction {
+                name: name.to_string(),
+            })),
+        }
+    }
+}
+
+impl fmt::Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.functions.keys()).finish()
+    }
+}
+
+/// Built-in transforms, they work on the string representation of a value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Transform {
+    Upper,
+    Lower,
+    Trim,
+    /// Number of characters as an `Int`.
+    Len,
+}
+
+impl Transform {
+    pub fn from_name(name: &str) -> Option<Transform> {
+        match name {
+            "upper" => Some(Transform::Upper),
+            "lower" => Some(Transform::Lower),
+            "trim" => Some(Transform::Trim),
+            "len" => Some(Transform::Len),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Transform::Upper => "upper",
+            Transform::Lower => "lower",
+            Transform::Trim => "trim",
+            Transform::Len => "len",
+        }
+    }
+
+    fn apply(self, val: VariableValue) -> VariableValue {
+        let val = match val {
+            VariableValue::Str(val) => val,
+            val => val.to_string(),
+        };
+        match self {
+            Transform::Upper => VariableValue::Str(val.to_uppercase()),
+            Transform::Lower => VariableValue::Str(val.to_lowercase()),
+            Transform::Trim => VariableValue::Str(val.trim().to_string()),
+            Transform::Len => VariableValue::Int(val.chars().count() as i64),
+        }
+    }
+}
+
+fn eval_number(
+    operand: &Ast,
+    variables: &impl VariableSource,
+    options: &EvalOptions,
+) -> Result<VariableValue, TauschError> {
+    let (name, val) = operand.eval_branch(variables, options)?;
+    match val {
+        VariableValue::Int(_) | VariableValue::Float(_) => Ok(val),
+        _ => Err(operand_type_mismatch(name, "number", &val)),
+    }
+}
+
+fn eval_str(
+    operand: &Ast,
+    variables: &impl VariableSource,
+    options: &EvalOptions,
+) -> Result<String, TauschError> {
+    let (name, val) = operand.eval_branch(variables, options)?;
+    match val {
+        VariableValue::Str(val) => Ok(val),
+        _ => Err(operand_type_mismatch(name, "str", &val)),
+    }
+}
+
+// `name` is the variable that produced `val`, if any.
+fn operand_type_mismatch(
+    name: Option<&str>,
+    expected: &'static str,
+    val: &VariableValue,
+) -> TauschError {
+    TauschError::Parser(match name {
+        Some(name) => ParserError::TypeMismatch {
+            name: name.to_string(),
+            expected,
+            found: val.type_name(),
+            span: None,
+        },
+        None => ParserError::OperandTypeMismatch {
+            expected,
+            found: val.type_name(),
+        },
+    })
+}
+
+/// Configures [`eval_with`], [`Ast::eval_with`] only uses the options that affect evaluation.
+#[derive(Clone, Debug, Default)]
+pub struct EvalOptions {
+    /// Accept `Str` variables containing "true" or "false" (ignoring case) as conditions.
+    pub coerce_bool_strings: bool,
+    /// Accept variables of any type as conditions, see [`VariableValue::is_truthy`].
+    pub truthiness: bool,
+    /// Result of an if-statement without an else-branch whose condition is false, `None`
+    /// means [`VariableValue::Empty`]. `??` only falls back if the result is `Empty`.
+    pub untaken_default: Option<VariableValue>,
+    /// See [`Tokenizer::with_case_insensitive_keywords`].
+    pub case_insensitive_keywords: bool,
+    /// See [`Tokenizer::with_max_tokens`].
+    pub max_tokens: Option<usize>,
+    pub parse: ParseOptions,
+    pub functions: FunctionRegistry,
+}
+
+impl Condition {
+    fn fold_constants(self) -> Condition {
+        match self {
+            Condition::Literal(_) | Condition::Var(_) | Condition::Defined(_) => self,
+            Condition::Not(cond) => match cond.fold_constants() {
+                Condition::Literal(val) => Condition::Literal(!val),
+                cond => Condition::Not(Box::new(cond)),
+            },
+            // Only drop operands that would not be evaluated or that don't change the result,
+            // 'a && false' still fails if 'a' does not exist.
+            Condition::And(lhs, rhs) => match (lhs.fold_constants(), rhs.fold_constants()) {
+                (Condition::Literal(false), _) => Condition::Literal(false),
+                (Condition::Literal(true), cond) | (cond, Condition::Literal(true)) => cond,
+                (lhs, rhs) => Condition::And(Box::new(lhs), Box::new(rhs)),
+            },
+            Condition::Or(lhs, rhs) => match (lhs.fold_constants(), rhs.fold_constants()) {
+                (Condition::Literal(true), _) => Condition::Literal(true),
+                (Condition::Literal(false), cond) | (cond, Condition::Literal(false)) => cond,
+                (lhs, rhs) => Condition::Or(Box::new(lhs), Box::new(rhs)),
+            },
+            Condition::Eq(lhs, rhs) => Condition::Eq(
+                Box::new(lhs.fold_constants()),
+                Box::new(rhs.fold_constants()),
+            )
+            .eval_constant(),
+            Condition::Contains(lhs, rhs) => Condition::Contains(
+                Box::new(lhs.fold_constants()),
+                Box::new(rhs.fold_constants()),
+            )
+            .eval_constant(),
+            Condition::Order(lhs, ordering, rhs) => Condition::Order(
+                Box::new(lhs.fold_constants()),
+                ordering,
+                Box::new(rhs.fold_constants()),
+            )
+            .eval_constant(),
+        }
+    }
+
+    fn eval_constant(self) -> Condition {
+        if let Condition::Eq(lhs, rhs)
+        | Condition::Contains(lhs, rhs)
+        | Condition::Order(lhs, _, rhs) = &self
+            && matches!((&**lhs, &**rhs), (Ast::Literal(_), Ast::Literal(_)))
+            && let Ok(val) = self.eval(&no_variables, &EvalOptions::default())
+        {
+            return Condition::Literal(val);
+        }
+        self
+    }
+
+    /// Number of nested nodes, the parser keeps it below [`ParseOptions::max_depth`].
+    fn height(&self) -> usize {
+        1 + match self {
+            Condition::Literal(_) | Condition::Var(_) | Condition::Defined(_) => 0,
+            Condition::Not(cond) => cond.height(),
+            Condition::Eq(lhs, rhs)
+            | Condition::Contains(lhs, rhs)
+            | Condition::Order(lhs, _, rhs) => lhs.height().max(rhs.height()),
+            Condition::And(lhs, rhs) | Condition::Or(lhs, rhs) => lhs.height().max(rhs.height()),
+        }
+    }
+
+    fn rename_variable(&mut self, from: &str, to: &str) -> usize {
+        match self {
+            Condition::Literal(_) => 0,
+            Condition::Var(name) | Condition::Defined(name) => rename(name, from, to),
+            Condition::Not(cond) => cond.rename_variable(from, to),
+            Condition::Eq(lhs, rhs)
+            | Condition::Contains(lhs, rhs)
+            | Condition::Order(lhs, _, rhs) => {
+                lhs.rename_variable(from, to) + rhs.rename_variable(from, to)
+            }
+            Condition::And(lhs, rhs) | Condition::Or(lhs, rhs) => {
+                lhs.rename_variable(from, to) + rhs.rename_variable(from, to)
+            }
+        }
+    }
+
+    fn collect_variables(&self, names: &mut Vec<String>) {
+        match self {
+            Condition::Literal(_) => {}
+            Condition::Var(name) | Condition::Defined(name) => push_unique(names, name),
+            Condition::Not(cond) => cond.collect_variables(names),
+            Condition::Eq(lhs, rhs)
+            | Condition::Contains(lhs, rhs)
+            | Condition::Order(lhs, _, rhs) => {
+                lhs.collect_variables(names);
+                rhs.collect_variables(names);
+            }
+            Condition::And(lhs, rhs) | Condition::Or(lhs, rhs) => {
+                lhs.collect_variables(names);
+                rhs.collect_variables(names);
+            }
+        }
+    }
+
+    fn eval(
+        &self,
+        variables: &impl VariableSource,
+        options: &EvalOptions,
+    ) -> Result<bool, TauschError> {
+        match self {
+            Condition::Literal(val) => Ok(*val),
+            Condition::Var(name) => {
+                let Some(var) = variables.get(name) else {
+                    return Err(TauschError::Parser(ParserError::UndefinedVariable {
+                        name: name.clone(),
+                        span: None,
+                    }));
+                };
+
+                match &var {
+                    VariableValue::Bool(val) => Ok(*val),
+                    VariableValue::Str(val)
+                        if options.coerce_bool_strings && val.eq_ignore_ascii_case("true") =>
+                    {
+                        Ok(true)
+                    }
+                    VariableValue::Str(val)
+                        if options.coerce_bool_strings && val.eq_ignore_ascii_case("false") =>
+                    {
+                        Ok(false)
+                    }
+                    _ if options.truthiness => Ok(var.is_truthy()),
+                    _ => Err(TauschError::Parser(ParserError::TypeMismatch {
+                        name: name.clone(),
+                        expected: "bool",
+                        found: var.type_name(),
+                        span: None,
+                    })),
+                }
+            }
+            Condition::Defined(name) => Ok(variables.get(name).is_some()),
+            Condition::Not(cond) => Ok(!cond.eval(variables, options)?),
+            Condition::Eq(lhs, rhs) => Ok(lhs
+                .eval_branch(variables, options)?
+                .1
+                .template_eq(&rhs.eval_branch(variables, options)?.1)),
+            Condition::Contains(needle, haystack) => {
+                let needle = eval_str(needle, variables, options)?;
+                let haystack = eval_str(haystack, variables, options)?;
+                Ok(haystack.contains(needle.as_str()))
+            }
+            Condition::Order(lhs, ordering, rhs) => {
+                let ord = match (
+                    eval_number(lhs, variables, options)?,
+                    eval_number(rhs, variables, options)?,
+                ) {
+                    (VariableValue::Int(lhs), VariableValue::Int(rhs)) => lhs.partial_cmp(&rhs),
+                    (VariableValue::Int(lhs), VariableValue::Float(rhs)) => {
+                        (lhs as f64).partial_cmp(&rhs)
+                    }
+                    (VariableValue::Float(lhs), VariableValue::Int(rhs)) => {
+                        lhs.partial_cmp(&(rhs as f64))
+                    }
+                    (VariableValue::Float(lhs), VariableValue::Float(rhs)) => lhs.partial_cmp(&rhs),
+                    _ => unreachable!("eval_number only returns numbers"),
+                };
+
+                // Comparisons involving NaN have no ordering and are always false.
+                let Some(ord) = ord else {
+                    return Ok(false);
+                };
+                Ok(match ordering {
+                    Ordering::Less => ord.is_lt(),
+                    Ordering::LessEq => ord.is_le(),
+                    Ordering::Greater => ord.is_gt(),
+                    Ordering::GreaterEq => ord.is_ge(),
+                })
+            }
+            Condition::And(lhs, rhs) => {
+                Ok(lhs.eval(variables, options)? && rhs.eval(variables, options)?)
+            }
+            Condition::Or(lhs, rhs) => {
+                Ok(lhs.eval(variables, options)? || rhs.eval(variables, options)?)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Ast {
+    Var(String),
+    Literal(VariableValue),
+    /// Values next to each other, joined into a single `Str` without a separator.
+    Concat(Vec<Ast>),
+    /// `lhs ?? rhs`, evaluates to `rhs` only if `lhs` is [`VariableValue::Empty`].
+    Coalesce(Box<Ast>, Box<Ast>),
+    /// Arithmetic on numbers, ints are promoted to floats if the other operand is a float.
+    Arith(Box<Ast>, ArithOp, Box<Ast>),
+    /// `value | transform`.
+    Transform(Box<Ast>, Transform),
+    /// `value[index]`, the character at `index` of a `Str`, negative indices count from the end.
+    Index(Box<Ast>, Box<Ast>),
+    /// `name(arg1 arg2)`, calls a function of [`EvalOptions::functions`].
+    Call {
+        name: String,
+        args: Vec<Ast>,
+    },
+    If {
+        cond: Condition,
+        on_true: Box<Ast>,
+        on_else: Option<Box<Ast>>,
+    },
+}
+
+impl Ast {
+    pub fn eval(&self, variables: &impl VariableSource) -> Result<VariableValue, TauschError> {
+        self.eval_with(variables, &EvalOptions::default())
+    }
+
+    pub fn eval_with(
+        &self,
+        variables: &impl VariableSource,
+        options: &EvalOptions,
+    ) -> Result<VariableValue, TauschError> {
+        Ok(self.eval_branch(variables, options)?.1)
+    }
+
+    /// Evaluates the expression once for every set of variables in `rows`.
+    pub fn eval_many<V: VariableSource>(
+        &self,
+        rows: &[V],
+    ) -> Vec<Result<VariableValue, TauschError>> {
+        rows.iter().map(|variables| self.eval(variables)).collect()
+    }
+
+    /// Like [`Ast::eval`], but also returns the name of the variable that produced the result.
+    ///
+    /// The name is `None` for literals and for an [`VariableValue::Empty`] result of an
+    /// if-statement without an else-branch.
+    pub fn eval_resolved(
+        &self,
+        variables: &impl VariableSource,
+    ) -> Result<(Option<String>, VariableValue), TauschError> {
+        let (name, val) = self.eval_branch(variables, &EvalOptions::default())?;
+        Ok((name.map(str::to_string), val))
+    }
+
+    /// Like [`Ast::eval`], but borrows the result from `variables` if it is a variable.
+    #[cfg(feature = "std")]
+    pub fn eval_ref<'a>(
+        &self,
+        variables: &'a HashMap<String, VariableValue>,
+    ) -> Result<ValueRef<'a>, TauschError> {
+        match self {
+            Ast::Var(name) => variables.get(name).map(ValueRef::Borrowed).ok_or_else(|| {
+                TauschError::Parser(ParserError::UndefinedVariable {
+                    name: name.clone(),
+                    span: None,
+                })
+            }),
+            Ast::If {
+                cond,
+                on_true,
+                on_else,
+            } => {
+                if cond.eval(variables, &EvalOptions::default())? {
+                    on_true.eval_ref(variables)
+                } else if let Some(on_else) = on_else {
+                    on_else.eval_ref(variables)
+                } else {
+                    Ok(ValueRef::Owned(VariableValue::Empty))
+                }
+            }
+            Ast::Coalesce(lhs, rhs) => match lhs.eval_ref(variables)? {
+                val if val.as_value().is_empty() => rhs.eval_ref(variables),
+                val => Ok(val),
+            },
+            _ => Ok(ValueRef::Owned(self.eval(variables)?)),
+        }
+    }
+
+    /// Like [`Ast::eval`], but also returns which branch of the outermost if-statement was
+    /// taken.
+    pub fn eval_traced(
+        &self,
+        variables: &impl VariableSource,
+    ) -> Result<(VariableValue, BranchTaken), TauschError> {
+        let Ast::If {
+            cond,
+            on_true,
+            on_else,
+        } = self
+        else {
+            return Ok((self.eval(variables)?, BranchTaken::NotAnIf));
+        };
+
+        if cond.eval(variables, &EvalOptions::default())? {
+            Ok((on_true.eval(variables)?, BranchTaken::True))
+        } else if let Some(on_else) = on_else {
+            Ok((on_else.eval(variables)?, BranchTaken::Else))
+        } else {
+            Ok((VariableValue::Empty, BranchTaken::None))
+        }
+    }
+
+    fn eval_branch(
+        &self,
+        variables: &impl VariableSource,
+        options: &EvalOptions,
+    ) -> Result<(Option<&str>, VariableValue), TauschError> {
+        match self {
+            Ast::Var(name) => match variables.get(name) {
+                Some(var) => Ok((Some(name), var)),
+                None => Err(TauschError::Parser(ParserError::UndefinedVariable {
+                    name: name.clone(),
+                    span: None,
+                })),
+            },
+            Ast::Literal(val) => Ok((None, val.clone())),
+            Ast::Concat(parts) => {
+                let mut joined = String::new();
+                for part in parts.iter() {
+                    joined.push_str(&part.eval_branch(variables, options)?.1.to_string());
+                }
+                Ok((None, VariableValue::Str(joined)))
+            }
+            Ast::Coalesce(lhs, rhs) => match lhs.eval_branch(variables, options)? {
+                (_, VariableValue::Empty) => rhs.eval_branch(variables, options),
+                resolved => Ok(resolved),
+            },
+            Ast::Transform(val, transform) => Ok((
+                None,
+                transform.apply(val.eval_branch(variables, options)?.1),
+            )),
+            Ast::Index(val, index) => {
+                let val = eval_str(val, variables, options)?;
+                let (name, index) = index.eval_branch(variables, options)?;
+                let VariableValue::Int(index) = index else {
+                    return Err(operand_type_mismatch(name, "int", &index));
+                };
+
+                let len = val.chars().count();
+                let pos = if index < 0 {
+                    usize::try_from(index.unsigned_abs())
+                        .ok()
+                        .and_then(|back| len.checked_sub(back))
+                } else {
+                    usize::try_from(index).ok()
+                };
+                match pos.and_then(|pos| val.chars().nth(pos)) {
+                    Some(c) => Ok((None, VariableValue::Str(c.to_string()))),
+                    None => Err(TauschError::Parser(ParserError::IndexOutOfRange {
+                        index,
+                        len,
+                    })),
+                }
+            }
+            Ast::Call { name, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| Ok(arg.eval_branch(variables, options)?.1))
+                    .collect::<Result<Vec<_>, TauschError>>()?;
+                Ok((None, options.functions.call(name, &args)?))
+            }
+            Ast::Arith(lhs, op, rhs) => {
+                let lhs = eval_number(lhs, variables, options)?;
+                let rhs = eval_number(rhs, variables, options)?;
+                Ok((None, op.apply(lhs, rhs)?))
+            }
+            Ast::If {
+                cond,
+                on_true,
+                on_else,
+            } => {
+                // Only the taken branch is evaluated, the other one may reference
+                // variables that do not exist.
+                if cond.eval(variables, options)? {
+                    on_true.eval_branch(variables, options)
+                } else if let Some(on_else) = on_else {
+                    on_else.eval_branch(variables, options)
+                } else {
+                    Ok((
+                        None,
+                        options
+                            .untaken_default
+                            .clone()
+                            .unwrap_or(VariableValue::Empty),
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Replaces if-statements with a literal condition by the taken branch and operations on
+    /// literals by their result. Operations that fail, e.g. a division by zero, are kept.
+    pub fn fold_constants(self) -> Ast {
+        let ast = match self {
+            Ast::Var(_) | Ast::Literal(_) => return self,
+            Ast::Concat(parts) => Ast::Concat(parts.into_iter().map(Ast::fold_constants).collect()),
+            Ast::Coalesce(lhs, rhs) => Ast::Coalesce(
+                Box::new(lhs.fold_constants()),
+                Box::new(rhs.fold_constants()),
+            ),
+            Ast::Arith(lhs, op, rhs) => Ast::Arith(
+                Box::new(lhs.fold_constants()),
+                op,
+                Box::new(rhs.fold_constants()),
+            ),
+            Ast::Transform(val, transform) => {
+                Ast::Transform(Box::new(val.fold_constants()), transform)
+            }
+            Ast::Index(val, index) => Ast::Index(
+                Box::new(val.fold_constants()),
+                Box::new(index.fold_constants()),
+            ),
+            Ast::Call { name, args } => {
+                return Ast::Call {
+                    name,
+                    args: args.into_iter().map(Ast::fold_constants).collect(),
+                };
+            }
+            // An untaken if-statement without an else-branch is kept, its result depends on
+            // EvalOptions::untaken_default.
+            Ast::If {
+                cond,
+                on_true,
+                on_else,
+            } => match (cond.fold_constants(), on_else) {
+                (Condition::Literal(true), _) => return on_true.fold_constants(),
+                (Condition::Literal(false), Some(on_else)) => return on_else.fold_constants(),
+                (cond, on_else) => {
+                    return Ast::If {
+                        cond,
+                        on_true: Box::new(on_true.fold_constants()),
+                        on_else: on_else.map(|on_else| Box::new(on_else.fold_constants())),
+                    };
+                }
+            },
+        };
+
+        if ast.is_constant()
+            && let Ok(val) = ast.eval(&no_variables)
+        {
+            return Ast::Literal(val);
+        }
+        ast
+    }
+
+    fn is_constant(&self) -> bool {
+        match self {
+            Ast::Literal(_) => true,
+            Ast::Var(_) | Ast::If { .. } | Ast::Call { .. } => false,
+            Ast::Concat(parts) => parts.iter().all(Ast::is_constant),
+            Ast::Coalesce(lhs, rhs) | Ast::Arith(lhs, _, rhs) | Ast::Index(lhs, rhs) => {
+                lhs.is_constant() && rhs.is_constant()
+            }
+            Ast::Transform(val, _) => val.is_constant(),
+        }
+    }
+
+    /// Number of nested nodes, the parser keeps it below [`ParseOptions::max_depth`].
+    fn height(&self) -> usize {
+        1 + match self {
+            Ast::Var(_) | Ast::Literal(_) => 0,
+            Ast::Concat(parts) | Ast::Call { args: parts, .. } => {
+                parts.iter().map(Ast::height).max().unwrap_or(0)
+            }
+            Ast::Coalesce(lhs, rhs) | Ast::Arith(lhs, _, rhs) | Ast::Index(lhs, rhs) => {
+                lhs.height().max(rhs.height())
+            }
+            Ast::Transform(val, _) => val.height(),
+            Ast::If {
+                cond,
+                on_true,
+                on_else,
+            } => cond
+                .height()
+                .max(on_true.height())
+                .max(on_else.as_ref().map_or(0, |on_else| on_else.height())),
+        }
+    }
+
+    /// Renames every reference to the variable `from` into `to` and returns the number of
+    /// renamed references.
+    pub fn rename_variable(&mut self, from: &str, to: &str) -> usize {
+        match self {
+            Ast::Var(name) => rename(name, from, to),
+            Ast::Literal(_) => 0,
+            Ast::Concat(parts) | Ast::Call { args: parts, .. } => parts
+                .iter_mut()
+                .map(|part| part.rename_variable(from, to))
+                .sum(),
+            Ast::Coalesce(lhs, rhs) | Ast::Arith(lhs, _, rhs) | Ast::Index(lhs, rhs) => {
+                lhs.rename_variable(from, to) + rhs.rename_variable(from, to)
+            }
+            Ast::Transform(val, _) => val.rename_variable(from, to),
+            Ast::If {
+                cond,
+                on_true,
+                on_else,
+            } => {
+                cond.rename_variable(from, to)
+                    + on_true.rename_variable(from, to)
+                    + on_else
+                        .as_mut()
+                        .map_or(0, |on_else| on_else.rename_variable(from, to))
+            }
+        }
+    }
+
+    /// Returns the distinct names of all variables the expression may look up, conditions
+    /// and untaken branches included, in order of their first appearance.
+    pub fn referenced_variables(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        self.collect_variables(&mut names);
+        names
+    }
+
+    /// Renders the expression as source with canonical spacing, e.g. `if cond ; a : b`.
+    /// Parsing the result yields an equal `Ast` for every tree created by [`parse`].
+    pub fn to_source(&self) -> String {
+        let mut writer = SourceWriter {
+            tokenizer: Tokenizer::new(),
+            out: String::new(),
+        };
+        writer.ast(self, Place::Expr);
+        writer.out
+    }
+
+    fn collect_variables(&self, names: &mut Vec<String>) {
+        match self {
+            Ast::Var(name) => push_unique(names, name),
+            Ast::Literal(_) => {}
+            Ast::Concat(parts) | Ast::Call { args: parts, .. } => {
+                for part in parts.iter() {
+                    part.collect_variables(names);
+                }
+            }
+            Ast::Coalesce(lhs, rhs) | Ast::Arith(lhs, _, rhs) | Ast::Index(lhs, rhs) => {
+                lhs.collect_variables(names);
+                rhs.collect_variables(names);
+            }
+            Ast::Transform(val, _) => val.collect_variables(names),
+            Ast::If {
+                cond,
+                on_true,
+                on_else,
+            } => {
+                cond.collect_variables(names);
+                on_true.collect_variables(names);
+                if let Some(on_else) = on_else {
+                    on_else.collect_variables(names);
+                }
+            }
+        }
+    }
+}
+
+/// Result of [`eval_ref`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValueRef<'a> {
+    /// The value of a variable.
+    Borrowed(&'a VariableValue),
+    /// A literal or computed value.
+    Owned(VariableValue),
+}
+
+impl ValueRef<'_> {
+    pub fn as_value(&self) -> &VariableValue {
+        match self {
+            ValueRef::Borrowed(val) => val,
+            ValueRef::Owned(val) => val,
+        }
+    }
+
+    pub fn into_owned(self) -> VariableValue {
+        match self {
+            ValueRef::Borrowed(val) => val.clone(),
+            ValueRef::Owned(val) => val,
+        }
+    }
+}
+
+impl fmt::Display for ValueRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_value().fmt(f)
+    }
+}
+
+/// See [`Ast::eval_traced`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BranchTaken {
+    True,
+    Else,
+    /// The condition was false and there is no else-branch.
+    None,
+    /// The expression is not an if-statement.
+    NotAnIf,
+}
+
+/// A non-fatal finding about an expression, see [`eval_with_diagnostics`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Ast {
+    fn collect_diagnostics(&self, diagnostics: &mut Vec<Diagnostic>) {
+        match self {
+            Ast::Var(_) | Ast::Literal(_) => {}
+            Ast::Concat(parts) | Ast::Call { args: parts, .. } => {
+                for part in parts.iter() {
+                    part.collect_diagnostics(diagnostics);
+                }
+            }
+            Ast::Coalesce(lhs, rhs) | Ast::Arith(lhs, _, rhs) | Ast::Index(lhs, rhs) => {
+                lhs.collect_diagnostics(diagnostics);
+                rhs.collect_diagnostics(diagnostics);
+            }
+            Ast::Transform(val, _) => val.collect_diagnostics(diagnostics),
+            Ast::If {
+                cond,
+                on_true,
+                on_else,
+            } => {
+                match (cond, on_else) {
+                    (Condition::Literal(true), Some(_)) => diagnostics.push(Diagnostic {
+                        message: "Unreachable else-branch, the condition is always true"
+                            .to_string(),
+                        span: None,
+                    }),
+                    (Condition::Literal(false), _) => diagnostics.push(Diagnostic {
+                        message: "Unreachable if-branch, the condition is always false".to_string(),
+                        span: None,
+                    }),
+                    _ => {}
+                }
+                on_true.collect_diagnostics(diagnostics);
+                if let Some(on_else) = on_else {
+                    on_else.collect_diagnostics(diagnostics);
+                }
+            }
+        }
+    }
+}
+
+fn no_variables(_: &str) -> Option<VariableValue> {
+    None
+}
+
+fn rename(name: &mut String, from: &str, to: &str) -> usize {
+    if name == from {
+        *name = to.to_string();
+        1
+    } else {
+        0
+    }
+}
+
+/// Where an expression is rendered, which decides whether it needs parentheses.
+#[derive(Clone, Copy, PartialEq)]
+enum Place {
+    /// The whole input or the inside of parentheses.
+    Expr,
+    /// A branch of an if-statement.
+    Branch,
+    /// A single value, e.g. a function argument or the operand of a transform.
+    Value,
+    /// The operand of an arithmetic operator or an index, only values and calls go without
+    /// parentheses.
+    Atom,
+}
+
+struct SourceWriter {
+    tokenizer: Tokenizer,
+    out: String,
+}
+
+impl SourceWriter {
+    fn ast(&mut self, ast: &Ast, place: Place) {
+        let parens = match ast {
+            Ast::Concat(_) | Ast::Coalesce(..) => place != Place::Expr,
+            Ast::If { .. } => matches!(place, Place::Value | Place::Atom),
+            Ast::Arith(..) | Ast::Transform(..) => place == Place::Atom,
+            _ => false,
+        };
+        if parens {
+            self.out.push('(');
+        }
+
+        match ast {
+            Ast::Var(name) => self.name(name),
+            Ast::Literal(val) => self.literal(val),
+            Ast::Concat(parts) => self.list(parts, " "),
+            Ast::Coalesce(lhs, rhs) => {
+                self.ast(lhs, Place::Expr);
+                self.out.push_str(" ?? ");
+                // '??' is left-associative, 'a ?? (b ?? c)' needs its parentheses.
+                let rhs_place = match **rhs {
+                    Ast::Coalesce(..) => Place::Value,
+                    _ => Place::Expr,
+                };
+                self.ast(rhs, rhs_place);
+            }
+            Ast::Arith(lhs, op, rhs) => {
+                // All operators are left-associative, so only a right-hand side of the same
+                // precedence needs parentheses, e.g. 'a - (b - c)'.
+                let lhs_place = match **lhs {
+                    Ast::Arith(_, lhs_op, _) if lhs_op.precedence() >= op.precedence() => {
+                        Place::Value
+                    }
+                    _ => Place::Atom,
+                };
+                self.ast(lhs, lhs_place);
+                self.out.push_str(match op {
+                    ArithOp::Add => " + ",
+                    ArithOp::Sub => " - ",
+                    ArithOp::Mul => " * ",
+                    ArithOp::Div => " / ",
+                });
+                let rhs_place = match **rhs {
+                    Ast::Arith(_, rhs_op, _) if rhs_op.precedence() > op.precedence() => {
+                        Place::Value
+                    }
+                    _ => Place::Atom,
+                };
+                self.ast(rhs, rhs_place);
+            }
+            Ast::Transform(val, transform) => {
+                self.ast(val, Place::Value);
+                self.out.push_str(" | ");
+                self.out.push_str(transform.name());
+            }
+            Ast::Index(val, index) => {
+                self.ast(val, Place::Atom);
+                self.out.push('[');
+                self.compared(index, false);
+                self.out.push(']');
+            }
+            Ast::Call { name, args } => {
+                self.name(name);
+                self.out.push('(');
+                self.list(args, " ");
+                self.out.push(')');
+            }
+            Ast::If {
+                cond,
+                on_true,
+                on_else,
+            } => {
+                self.out.push_str("if ");
+                self.condition(cond, 0);
+                self.out.push_str(" ; ");
+                // An ':' belongs to the innermost if-statement without an else-branch.
+                if on_else.is_some() && ends_without_else(on_true) {
+                    self.out.push('(');
+                    self.ast(on_true, Place::Expr);
+                    self.out.push(')');
+                } else {
+                    self.ast(on_true, Place::Branch);
+                }
+                if let Some(on_else) = on_else {
+                    self.out.push_str(" : ");
+                    self.ast(on_else, Place::Branch);
+                }
+            }
+        }
+
+        if parens {
+            self.out.push(')');
+        }
+    }
+
+    fn list(&mut self, asts: &[Ast], separator: &str) {
+        for (i, ast) in asts.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(separator);
+            }
+            self.ast(ast, Place::Value);
+        }
+    }
+
+    /// `prec` is 0 for the operand of '||', 1 for '&&' and 2 for everything binding stronger.
+    fn condition(&mut self, cond: &Condition, prec: u8) {
+        match cond {
+            Condition::Literal(val) => self.out.push_str(if *val { "true" } else { "false" }),
+            Condition::Var(name) => self.name(name),
+            Condition::Defined(name) => {
+                self.out.push_str("defined(");
+                self.name(name);
+                self.out.push(')');
+            }
+            Condition::Not(cond) => match &**cond {
+                Condition::Eq(lhs, rhs) => self.comparison(lhs, " != ", rhs, true),
+                Condition::Not(_) | Condition::And(..) | Condition::Or(..) => {
+                    self.out.push_str("!(");
+                    self.condition(cond, 0);
+                    self.out.push(')');
+                }
+                cond => {
+                    self.out.push('!');
+                    self.condition(cond, 2);
+                }
+            },
+            Condition::Eq(lhs, rhs) => self.comparison(lhs, " == ", rhs, true),
+            Condition::Contains(lhs, rhs) => {
+                if matches!(**lhs, Ast::Var(_) | Ast::Literal(VariableValue::Str(_))) {
+                    self.ast(lhs, Place::Value);
+                } else {
+                    self.group(lhs);
+                }
+                self.out.push_str(" in ");
+                self.compared(rhs, true);
+            }
+            Condition::Order(lhs, ordering, rhs) => {
+                let op = match ordering {
+                    Ordering::Less => " < ",
+                    Ordering::LessEq => " <= ",
+                    Ordering::Greater => " > ",
+                    Ordering::GreaterEq => " >= ",
+                };
+                self.comparison(lhs, op, rhs, false);
+            }
+            Condition::And(lhs, rhs) | Condition::Or(lhs, rhs) => {
+                let (op, own) = match cond {
+                    Condition::And(..) => (" && ", 1),
+                    _ => (" || ", 0),
+                };
+                if prec > own {
+                    self.out.push('(');
+                }
+                self.condition(lhs, own);
+                self.out.push_str(op);
+                self.condition(rhs, own + 1);
+                if prec > own {
+                    self.out.push(')');
+                }
+            }
+        }
+    }
+
+    fn comparison(&mut self, lhs: &Ast, op: &str, rhs: &Ast, eq: bool) {
+        self.compared(lhs, eq);
+        self.out.push_str(op);
+        self.compared(rhs, eq);
+    }
+
+    /// An operand of a comparison, anything but a literal or number operand is grouped.
+    fn compared(&mut self, ast: &Ast, eq: bool) {
+        if is_number_operand(ast)
+            || (eq
+                && matches!(
+                    ast,
+                    Ast::Literal(VariableValue::Str(_) | VariableValue::Bool(_))
+                ))
+        {
+            self.ast(ast, Place::Value);
+        } else {
+            self.group(ast);
+        }
+    }
+
+    fn group(&mut self, ast: &Ast) {
+        self.out.push('(');
+        self.ast(ast, Place::Expr);
+        self.out.push(')');
+    }
+
+    fn literal(&mut self, val: &VariableValue) {
+        match val {
+            VariableValue::Str(val) => {
+                self.out.push('"');
+                for c in val.chars() {
+                    if matches!(c, '"' | '\\') {
+                        self.out.push('\\');
+                    }
+                    self.out.push(c);
+                }
+                self.out.push('"');
+            }
+            // Float literals always contain a '.', '1.0' would be printed as '1' otherwise.
+            VariableValue::Float(val) => {
+                let start = self.out.len();
+                self.out.push_str(&val.to_string());
+                if !self.out[start..].contains('.') {
+                    self.out.push_str(".0");
+                }
+            }
+            val => self.out.push_str(&val.to_string()),
+        }
+    }
+
+    // Keywords and characters that can't be part of a name are escaped with '\\'.
+    fn name(&mut self, name: &str) {
+        let is_keyword = self
+            .tokenizer
+            .reserved_tokens
+            .iter()
+            .any(|tok| tok.label == name);
+        for (i, c) in name.chars().enumerate() {
+            let plain = if i == 0 {
+                !is_keyword && self.tokenizer.is_var_name_start(c)
+            } else {
+                self.tokenizer.is_allowed_var_name(c)
+            };
+            if !plain {
+                self.out.push('\\');
+            }
+            self.out.push(c);
+        }
+    }
+}
+
+fn ends_without_else(ast: &Ast) -> bool {
+    match ast {
+        Ast::If { on_else: None, .. } => true,
+        Ast::If {
+            on_else: Some(on_else),
+            ..
+        } => ends_without_else(on_else),
+        _ => false,
+    }
+}
+
+/// Whether `ast` can be parsed as the operand of an ordering, e.g. `a * 2`.
+fn is_number_operand(ast: &Ast) -> bool {
+    match ast {
+        Ast::Var(_) | Ast::Literal(VariableValue::Int(_) | VariableValue::Float(_)) => true,
+        Ast::Arith(lhs, _, rhs) => is_number_operand(lhs) && is_number_operand(rhs),
+        _ => false,
+    }
+}
+
+fn push_unique(names: &mut Vec<String>, name: &str) {
+    if !names.iter().any(|known| known == name) {
+        names.push(name.to_string());
+    }
+}
+
+fn parse_branch(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Ast, TauschError> {
+    if let Some(tok) = accept_token(iterator, TokenType::IfStart)
+        .or_else(|| accept_token(iterator, TokenType::UnlessStart))
+    {
+        return parse_if(iterator, options, depth + 1, &tok);
+    }
+    if let Some(tok) = accept_token(iterator, TokenType::ParenOpen) {
+        let group = parse_group(iterator, options, depth + 1, &tok)?;
+        return parse_postfix(iterator, options, depth, group);
+    }
+
+    match iterator.next() {
+        Some(tok) if is_value_token(tok) => parse_value_part(iterator, options, depth, tok),
+        Some(tok) => Err(TauschError::Parser(ParserError::UnexpectedToken {
+            expected: "Expected value or if-statement inside of 'if'-branch of if-statement."
+                .to_string(),
+            expected_tokens: value_tokens_and(&[
+                TokenType::IfStart,
+                TokenType::UnlessStart,
+                TokenType::ParenOpen,
+            ]),
+            found: tok.label.to_string(),
+            column: tok.column,
+            span: tok.span,
+        })),
+        None => Err(TauschError::Parser(ParserError::UnexpectedEof {
+            expected: "Expected value or if-statement inside of 'if'-branch of if-statement."
+                .to_string(),
+            expected_tokens: value_tokens_and(&[
+                TokenType::IfStart,
+                TokenType::UnlessStart,
+                TokenType::ParenOpen,
+            ]),
+        })),
+    }
+}
+
+/// A value or function call followed by indexing, arithmetic and transforms.
+fn parse_value_part(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    tok: &Token,
+) -> Result<Ast, TauschError> {
+    let val = if tok.typ == TokenType::Variable
+        && let Some(open_tok) = accept_token(iterator, TokenType::ParenOpen)
+    {
+        parse_call(iterator, options, depth + 1, tok, &open_tok)?
+    } else {
+        parse_value(tok)?
+    };
+    parse_postfix(iterator, options, depth, val)
+}
+
+/// Indexing, arithmetic and transforms after a value, call or group.
+fn parse_postfix(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    val: Ast,
+) -> Result<Ast, TauschError> {
+    let val = parse_indexes(iterator, options, depth, val)?;
+    let val = parse_arith_rest(iterator, options, depth, val, parse_value_atom)?;
+    parse_transforms(iterator, options, depth, val)
+}
+
+fn parse_indexes(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    mut val: Ast,
+) -> Result<Ast, TauschError> {
+    while let Some(open_tok) = accept_token(iterator, TokenType::BracketOpen) {
+        let first = parse_index_atom(iterator, options, depth)?;
+        let index = parse_arith_rest(iterator, options, depth, first, parse_index_atom)?;
+        expect_token(
+            iterator,
+            TokenType::BracketClose,
+            "Expected ']' to close the '[' of the index!".to_string(),
+        )?;
+        val = Ast::Index(Box::new(val), Box::new(index));
+        check_depth(options, depth + val.height(), &open_tok)?;
+    }
+    Ok(val)
+}
+
+fn parse_call(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    name_tok: &Token,
+    open_tok: &Token,
+) -> Result<Ast, TauschError> {
+    check_depth(options, depth, open_tok)?;
+    let mut args = Vec::new();
+    loop {
+        match iterator.next() {
+            Some(tok) if tok.typ == TokenType::ParenClose => break,
+            Some(tok) if tok.typ == TokenType::ParenOpen => {
+                let group = parse_group(iterator, options, depth + 1, tok)?;
+                args.push(parse_postfix(iterator, options, depth, group)?);
+            }
+            Some(tok) if is_value_token(tok) => {
+                args.push(parse_value_part(iterator, options, depth, tok)?);
+            }
+            Some(tok) => {
+                return Err(TauschError::Parser(ParserError::UnexpectedToken {
+                    expected: "Expected argument or ')' inside of a function call!".to_string(),
+                    expected_tokens: value_tokens_and(&[
+                        TokenType::ParenClose,
+                        TokenType::ParenOpen,
+                    ]),
+                    found: tok.label.to_string(),
+                    column: tok.column,
+                    span: tok.span,
+                }));
+            }
+            None => {
+                return Err(TauschError::Parser(ParserError::UnexpectedEof {
+                    expected: "Expected ')' to close the '(' of the function call!".to_string(),
+                    expected_tokens: value_tokens_and(&[
+                        TokenType::ParenClose,
+                        TokenType::ParenOpen,
+                    ]),
+                }));
+            }
+        }
+    }
+    Ok(Ast::Call {
+        name: name_tok.label.to_string(),
+        args,
+    })
+}
+
+fn parse_transforms(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    mut val: Ast,
+) -> Result<Ast, TauschError> {
+    while let Some(pipe_tok) = accept_token(iterator, TokenType::Pipe) {
+        let tok = expect_token(
+            iterator,
+            TokenType::Variable,
+            "Expected name of a transform after '|'!".to_string(),
+        )?;
+        let Some(transform) = Transform::from_name(&tok.label) else {
+            return Err(TauschError::Parser(ParserError::UnknownTransform {
+                name: tok.label.into_owned(),
+                column: tok.column,
+                span: tok.span,
+            }));
+        };
+        val = Ast::Transform(Box::new(val), transform);
+        check_depth(options, depth + val.height(), &pipe_tok)?;
+    }
+    Ok(val)
+}
+
+fn accept_arith_op<'t, 'a>(
+    iterator: &mut core::slice::Iter<'t, Token<'a>>,
+    ops: &[(TokenType, ArithOp)],
+) -> Option<(ArithOp, &'t Token<'a>)> {
+    let tok = iterator.as_slice().first()?;
+    let (_, op) = ops.iter().find(|(typ, _)| *typ == tok.typ)?;
+    iterator.next();
+    Some((*op, tok))
+}
+
+type AtomParser =
+    fn(&mut core::slice::Iter<Token>, &ParseOptions, usize) -> Result<Ast, TauschError>;
+
+fn parse_term_rest(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    mut lhs: Ast,
+    atom: AtomParser,
+) -> Result<Ast, TauschError> {
+    while let Some((op, tok)) = accept_arith_op(
+        iterator,
+        &[
+            (TokenType::Star, ArithOp::Mul),
+            (TokenType::Slash, ArithOp::Div),
+        ],
+    ) {
+        lhs = Ast::Arith(Box::new(lhs), op, Box::new(atom(iterator, options, depth)?));
+        check_depth(options, depth + lhs.height(), tok)?;
+    }
+    Ok(lhs)
+}
+
+// '*' and '/' bind stronger than '+' and '-', all of them are left-associative.
+fn parse_arith_rest(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    first: Ast,
+    atom: AtomParser,
+) -> Result<Ast, TauschError> {
+    let mut lhs = parse_term_rest(iterator, options, depth, first, atom)?;
+    while let Some((op, tok)) = accept_arith_op(
+        iterator,
+        &[
+            (TokenType::Plus, ArithOp::Add),
+            (TokenType::Minus, ArithOp::Sub),
+        ],
+    ) {
+        let rhs = atom(iterator, options, depth)?;
+        let rhs = parse_term_rest(iterator, options, depth, rhs, atom)?;
+        lhs = Ast::Arith(Box::new(lhs), op, Box::new(rhs));
+        check_depth(options, depth + lhs.height(), tok)?;
+    }
+    Ok(lhs)
+}
+
+fn parse_value_atom(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Ast, TauschError> {
+    let val = match iterator.next() {
+        Some(tok) if tok.typ == TokenType::ParenOpen => {
+            parse_group(iterator, options, depth + 1, tok)?
+        }
+        Some(tok) if is_value_token(tok) => parse_value(tok)?,
+        Some(tok) => {
+            return Err(TauschError::Parser(ParserError::UnexpectedToken {
+                expected: "Expected value after arithmetic operator!".to_string(),
+                expected_tokens: value_tokens_and(&[TokenType::ParenOpen]),
+                found: tok.label.to_string(),
+                column: tok.column,
+                span: tok.span,
+            }));
+        }
+        None => {
+            return Err(TauschError::Parser(ParserError::UnexpectedEof {
+                expected: "Expected value after arithmetic operator!".to_string(),
+                expected_tokens: value_tokens_and(&[TokenType::ParenOpen]),
+            }));
+        }
+    };
+    parse_indexes(iterator, options, depth, val)
+}
+
+fn parse_number_operand(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Ast, TauschError> {
+    let first = parse_number_atom(iterator, options, depth)?;
+    parse_arith_rest(iterator, options, depth, first, parse_number_atom)
+}
+
+fn parse_number_atom(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Ast, TauschError> {
+    parse_number_atom_in(
+        iterator,
+        options,
+        depth,
+        "Expected variable name or number inside of the comparison of 'if'!",
+    )
+}
+
+fn parse_index_atom(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Ast, TauschError> {
+    parse_number_atom_in(
+        iterator,
+        options,
+        depth,
+        "Expected variable name or number as the index inside of '['!",
+    )
+}
+
+// `expected` is the message of the error if the next token is not a number operand.
+fn parse_number_atom_in(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    expected: &str,
+) -> Result<Ast, TauschError> {
+    match iterator.next() {
+        Some(tok) if tok.typ == TokenType::ParenOpen => {
+            parse_group(iterator, options, depth + 1, tok)
+        }
+        Some(tok)
+            if matches!(
+                tok.typ,
+                TokenType::Variable | TokenType::IntLiteral | TokenType::FloatLiteral
+            ) =>
+        {
+            parse_value(tok)
+        }
+        Some(tok) => Err(TauschError::Parser(ParserError::UnexpectedToken {
+            expected: expected.to_string(),
+            expected_tokens: vec![
+                TokenType::ParenOpen,
+                TokenType::Variable,
+                TokenType::IntLiteral,
+                TokenType::FloatLiteral,
+            ],
+            found: tok.label.to_string(),
+            column: tok.column,
+            span: tok.span,
+        })),
+        None => Err(TauschError::Parser(ParserError::UnexpectedEof {
+            expected: expected.to_string(),
+            expected_tokens: vec![
+                TokenType::ParenOpen,
+                TokenType::Variable,
+                TokenType::IntLiteral,
+                TokenType::FloatLiteral,
+            ],
+        })),
+    }
+}
+
+fn accept_ordering(iterator: &mut core::slice::Iter<Token>) -> Option<Ordering> {
+    let ordering = match iterator.as_slice().first()?.typ {
+        TokenType::Less => Ordering::Less,
+        TokenType::LessEq => Ordering::LessEq,
+        TokenType::Greater => Ordering::Greater,
+        TokenType::GreaterEq => Ordering::GreaterEq,
+        _ => return None,
+    };
+    iterator.next();
+    Some(ordering)
+}
+
+fn parse_comparison(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Condition, TauschError> {
+    if let Some(open_tok) = iterator.as_slice().first()
+        && open_tok.typ == TokenType::ParenOpen
+        && is_compared_group(iterator.as_slice())
+    {
+        let lhs = parse_number_operand(iterator, options, depth)?;
+        if let Some(eq) = accept_equality(iterator) {
+            return parse_equality_rest(iterator, options, depth, lhs, eq);
+        }
+        if accept_token(iterator, TokenType::In).is_some() {
+            return parse_contains_rest(iterator, options, depth, lhs);
+        }
+        let Some(ordering) = accept_ordering(iterator) else {
+            return Err(unexpected_in_condition(
+                iterator,
+                "Expected '<', '<=', '>', '>=', '==', '!=' or 'in' after the parenthesized value inside of the condition of 'if'!",
+                &[
+                    TokenType::Less,
+                    TokenType::LessEq,
+                    TokenType::Greater,
+                    TokenType::GreaterEq,
+                    TokenType::Eq,
+                    TokenType::NotEq,
+                    TokenType::In,
+                ],
+            ));
+        };
+        let rhs = parse_number_operand(iterator, options, depth)?;
+        return Ok(Condition::Order(Box::new(lhs), ordering, Box::new(rhs)));
+    }
+
+    if let Some(tok) = accept_token(iterator, TokenType::ParenOpen) {
+        check_depth(options, depth + 1, &tok)?;
+        let cond = parse_condition(iterator, options, depth + 1)?;
+        expect_token(
+            iterator,
+            TokenType::ParenClose,
+            "Expected ')' to close the '(' inside of the condition of 'if'!".to_string(),
+        )?;
+        return Ok(cond);
+    }
+
+    if let Some(tok) = accept_token(iterator, TokenType::BoolLiteral) {
+        let val = parse_bool(&tok)?;
+        return match accept_equality(iterator) {
+            Some(eq) => parse_equality_rest(
+                iterator,
+                options,
+                depth,
+                Ast::Literal(VariableValue::Bool(val)),
+                eq,
+            ),
+            None => Ok(Condition::Literal(val)),
+        };
+    }
+
+    if let Some(tok) = accept_token(iterator, TokenType::StrLiteral) {
+        let lhs = parse_value(&tok)?;
+        if accept_token(iterator, TokenType::In).is_some() {
+            return parse_contains_rest(iterator, options, depth, lhs);
+        }
+        let Some(eq) = accept_equality(iterator) else {
+            return Err(unexpected_in_condition(
+                iterator,
+                "Expected '==', '!=' or 'in' after string inside of the condition of 'if'!",
+                &[TokenType::Eq, TokenType::NotEq, TokenType::In],
+            ));
+        };
+        return parse_equality_rest(iterator, options, depth, lhs, eq);
+    }
+
+    if let [tok, rest @ ..] = iterator.as_slice()
+        && (matches!(tok.typ, TokenType::IntLiteral | TokenType::FloatLiteral)
+            || (tok.typ == TokenType::Variable
+                && rest.first().is_some_and(|op| {
+                    matches!(
+                        op.typ,
+                        TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash
+                    )
+                })))
+    {
+        let lhs = parse_number_operand(iterator, options, depth)?;
+        if let Some(eq) = accept_equality(iterator) {
+            return parse_equality_rest(iterator, options, depth, lhs, eq);
+        }
+        let Some(ordering) = accept_ordering(iterator) else {
+            return Err(unexpected_in_condition(
+                iterator,
+                "Expected '<', '<=', '>', '>=', '==' or '!=' after number inside of the condition of 'if'!",
+                &[
+                    TokenType::Less,
+                    TokenType::LessEq,
+                    TokenType::Greater,
+                    TokenType::GreaterEq,
+                    TokenType::Eq,
+                    TokenType::NotEq,
+                ],
+            ));
+        };
+        let rhs = parse_number_operand(iterator, options, depth)?;
+        return Ok(Condition::Order(Box::new(lhs), ordering, Box::new(rhs)));
+    }
+
+    let tok_condition = expect_token(
+        iterator,
+        TokenType::Variable,
+        "Expected variable name inside of the condition of 'if'!".to_string(),
+    )?;
+    let name = tok_condition.label.into_owned();
+
+    if name == "defined" && accept_token(iterator, TokenType::ParenOpen).is_some() {
+        let tok_name = expect_token(
+            iterator,
+            TokenType::Variable,
+            "Expected variable name inside of 'defined('!".to_string(),
+        )?;
+        expect_token(
+            iterator,
+            TokenType::ParenClose,
+            "Expected ')' to close the '(' of 'defined'!".to_string(),
+        )?;
+        return Ok(Condition::Defined(tok_name.label.into_owned()));
+    }
+
+    if let Some(ordering) = accept_ordering(iterator) {
+        let rhs = parse_number_operand(iterator, options, depth)?;
+        return Ok(Condition::Order(
+            Box::new(Ast::Var(name)),
+            ordering,
+            Box::new(rhs),
+        ));
+    }
+
+    if accept_token(iterator, TokenType::In).is_some() {
+        return parse_contains_rest(iterator, options, depth, Ast::Var(name));
+    }
+
+    match accept_equality(iterator) {
+        Some(eq) => parse_equality_rest(iterator, options, depth, Ast::Var(name), eq),
+        None => Ok(Condition::Var(name)),
+    }
+}
+
+/// Whether the parenthesized group at the start of `tokens` is followed by a comparison
+/// operator, which makes it a value like `(if c ; a : b) == d` instead of a group of
+/// conditions.
+fn is_compared_group(tokens: &[Token]) -> bool {
+    let mut depth = 0usize;
+    for (i, tok) in tokens.iter().enumerate() {
+        match tok.typ {
+            TokenType::ParenOpen => depth += 1,
+            TokenType::ParenClose => {
+                depth -= 1;
+                if depth == 0 {
+                    return tokens.get(i + 1).is_some_and(|next| {
+                        matches!(
+                            next.typ,
+                            TokenType::Eq
+                                | TokenType::NotEq
+                                | TokenType::In
+                                | TokenType::Plus
+                                | TokenType::Minus
+                                | TokenType::Star
+                                | TokenType::Slash
+                                | TokenType::Less
+                                | TokenType::LessEq
+                                | TokenType::Greater
+                                | TokenType::GreaterEq
+                        )
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Returns whether the accepted operator is `==` (`true`) or `!=` (`false`).
+fn accept_equality(iterator: &mut core::slice::Iter<Token>) -> Option<bool> {
+    if accept_token(iterator, TokenType::Eq).is_some() {
+        Some(true)
+    } else {
+        accept_token(iterator, TokenType::NotEq).map(|_| false)
+    }
+}
+
+fn parse_equality_rest(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    lhs: Ast,
+    eq: bool,
+) -> Result<Condition, TauschError> {
+    let rhs = parse_compared_operand(iterator, options, depth)?;
+    let cond = Condition::Eq(Box::new(lhs), Box::new(rhs));
+    Ok(if eq {
+        cond
+    } else {
+        Condition::Not(Box::new(cond))
+    })
+}
+
+fn parse_contains_rest(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    needle: Ast,
+) -> Result<Condition, TauschError> {
+    let haystack = parse_compared_operand(iterator, options, depth)?;
+    Ok(Condition::Contains(Box::new(needle), Box::new(haystack)))
+}
+
+/// The right-hand side of '==', '!=' or 'in'.
+fn parse_compared_operand(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Ast, TauschError> {
+    Ok(match iterator.as_slice().first() {
+        Some(tok) if matches!(tok.typ, TokenType::StrLiteral | TokenType::BoolLiteral) => {
+            iterator.next();
+            parse_value(tok)?
+        }
+        Some(tok)
+            if matches!(
+                tok.typ,
+                TokenType::ParenOpen
+                    | TokenType::Variable
+                    | TokenType::IntLiteral
+                    | TokenType::FloatLiteral
+            ) =>
+        {
+            parse_number_operand(iterator, options, depth)?
+        }
+        _ => {
+            return Err(unexpected_in_condition(
+                iterator,
+                "Expected variable name or literal after comparison inside of the condition of 'if'!",
+                &[
+                    TokenType::ParenOpen,
+                    TokenType::Variable,
+                    TokenType::IntLiteral,
+                    TokenType::FloatLiteral,
+                    TokenType::StrLiteral,
+                    TokenType::BoolLiteral,
+                ],
+            ));
+        }
+    })
+}
+
+fn unexpected_in_condition(
+    iterator: &mut core::slice::Iter<Token>,
+    expected: &str,
+    expected_tokens: &[TokenType],
+) -> TauschError {
+    TauschError::Parser(match iterator.as_slice().first() {
+        Some(tok) => ParserError::UnexpectedToken {
+            expected: expected.to_string(),
+            expected_tokens: expected_tokens.to_vec(),
+            found: tok.label.to_string(),
+            column: tok.column,
+            span: tok.span,
+        },
+        None => ParserError::UnexpectedEof {
+            expected: expected.to_string(),
+            expected_tokens: expected_tokens.to_vec(),
+        },
+    })
+}
+
+fn parse_condition_operand(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Condition, TauschError> {
+    // '!' negates the whole comparison that follows: '!a == b' means 'a != b'.
+    let cond = if accept_token(iterator, TokenType::IfNegate).is_some() {
+        Condition::Not(Box::new(parse_comparison(iterator, options, depth)?))
+    } else {
+        parse_comparison(iterator, options, depth)?
+    };
+
+    // 'a == b == c' could mean '(a == b) == c' or 'a == b && b == c', so it is rejected.
+    if let Some(tok) = iterator.as_slice().first()
+        && matches!(
+            tok.typ,
+            TokenType::Eq
+                | TokenType::NotEq
+                | TokenType::In
+                | TokenType::Less
+                | TokenType::LessEq
+                | TokenType::Greater
+                | TokenType::GreaterEq
+        )
+    {
+        return Err(TauschError::Parser(ParserError::UnexpectedToken {
+            expected: "Comparisons can't be chained, combine them with '&&' instead!".to_string(),
+            expected_tokens: Vec::new(),
+            found: tok.label.to_string(),
+            column: tok.column,
+            span: tok.span,
+        }));
+    }
+    Ok(cond)
+}
+
+fn parse_condition_and(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Condition, TauschError> {
+    let mut cond = parse_condition_operand(iterator, options, depth)?;
+    while let Some(tok) = accept_token(iterator, TokenType::And) {
+        cond = Condition::And(
+            Box::new(cond),
+            Box::new(parse_condition_operand(iterator, options, depth)?),
+        );
+        check_depth(options, depth + cond.height(), &tok)?;
+    }
+    Ok(cond)
+}
+
+fn parse_condition(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Condition, TauschError> {
+    let mut cond = parse_condition_and(iterator, options, depth)?;
+    while let Some(tok) = accept_token(iterator, TokenType::Or) {
+        cond = Condition::Or(
+            Box::new(cond),
+            Box::new(parse_condition_and(iterator, options, depth)?),
+        );
+        check_depth(options, depth + cond.height(), &tok)?;
+    }
+    Ok(cond)
+}
+
+// Nested 'if's and parentheses recurse, so deeply nested input could overflow the stack otherwise.
+fn check_depth(options: &ParseOptions, depth: usize, tok: &Token) -> Result<(), TauschError> {
+    if depth > options.max_depth {
+        return Err(TauschError::Parser(ParserError::NestingTooDeep {
+            max_depth: options.max_depth,
+            column: tok.column,
+            span: tok.span,
+        }));
+    }
+    Ok(())
+}
+
+fn parse_if(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    if_tok: &Token,
+) -> Result<Ast, TauschError> {
+    check_depth(options, depth, if_tok)?;
+
+    let mut cond = parse_condition(iterator, options, depth)?;
+    if if_tok.typ == TokenType::UnlessStart {
+        cond = Condition::Not(Box::new(cond));
+    }
+
+    expect_token(
+        iterator,
+        TokenType::IfEnd,
+        "Expected ';' after the condition of 'if'!".to_string(),
+    )?;
+
+    let on_true = parse_branch(iterator, options, depth)?;
+
+    if accept_token(iterator, TokenType::IfElse).is_none() {
+        return Ok(Ast::If {
+            cond,
+            on_true: Box::new(on_true),
+            on_else: None,
+        });
+    }
+
+    // 'if a ; x : b ; y : z' is sugar for 'if a ; x : if b ; y : z'.
+    let on_else = if let Some(tok) = iterator.as_slice().first()
+        && is_else_if(iterator.as_slice())
+    {
+        parse_if(iterator, options, depth + 1, tok)?
+    } else {
+        parse_branch(iterator, options, depth)?
+    };
+
+    Ok(Ast::If {
+        cond,
+        on_true: Box::new(on_true),
+        on_else: Some(Box::new(on_else)),
+    })
+}
+
+/// Whether the else-branch at the start of `tokens` is a condition followed by `;`. Only the
+/// tokens are scanned, parsing the condition speculatively would take exponential time for
+/// conditions that contain else-ifs themselves.
+fn is_else_if(tokens: &[Token]) -> bool {
+    let mut depth = 0usize;
+    for tok in tokens {
+        match tok.typ {
+            TokenType::ParenOpen => depth += 1,
+            TokenType::ParenClose if depth == 0 => return false,
+            TokenType::ParenClose => depth -= 1,
+            _ if depth > 0 => {}
+            TokenType::IfEnd => return true,
+            TokenType::Variable
+            | TokenType::IntLiteral
+            | TokenType::FloatLiteral
+            | TokenType::StrLiteral
+            | TokenType::BoolLiteral
+            | TokenType::IfNegate
+            | TokenType::And
+            | TokenType::Or
+            | TokenType::Eq
+            | TokenType::NotEq
+            | TokenType::In
+            | TokenType::Less
+            | TokenType::LessEq
+            | TokenType::Greater
+            | TokenType::GreaterEq
+            | TokenType::Plus
+            | TokenType::Minus
+            | TokenType::Star
+            | TokenType::Slash => {}
+            _ => return false,
+        }
+    }
+    false
+}
+
+const VALUE_TOKENS: [TokenType; 5] = [
+    TokenType::Variable,
+    TokenType::IntLiteral,
+    TokenType::FloatLiteral,
+    TokenType::StrLiteral,
+    TokenType::BoolLiteral,
+];
+
+fn is_value_token(tok: &Token) -> bool {
+    VALUE_TOKENS.contains(&tok.typ)
+}
+
+fn value_tokens_and(others: &[TokenType]) -> Vec<TokenType> {
+    let mut tokens = others.to_vec();
+    tokens.extend(VALUE_TOKENS);
+    tokens
+}
+
+fn parse_bool(tok: &Token) -> Result<bool, TauschError> {
+    // Case-insensitive keywords may produce 'TRUE' or 'False'.
+    if tok.label.eq_ignore_ascii_case("true") {
+        Ok(true)
+    } else if tok.label.eq_ignore_ascii_case("false") {
+        Ok(false)
+    } else {
+        Err(TauschError::Parser(ParserError::InvalidLiteral(
+            tok.label.to_string(),
+        )))
+    }
+}
+
+fn parse_value(tok: &Token) -> Result<Ast, TauschError> {
+    match tok.typ {
+        TokenType::IntLiteral => match tok.label.parse() {
+            Ok(val) => Ok(Ast::Literal(VariableValue::Int(val))),
+            Err(_) => Err(TauschError::Parser(ParserError::InvalidLiteral(
+                tok.label.to_string(),
+            ))),
+        },
+        TokenType::FloatLiteral => match tok.label.parse() {
+            Ok(val) => Ok(Ast::Literal(VariableValue::Float(val))),
+            Err(_) => Err(TauschError::Parser(ParserError::InvalidLiteral(
+                tok.label.to_string(),
+            ))),
+        },
+        TokenType::StrLiteral => Ok(Ast::Literal(VariableValue::Str(tok.label.to_string()))),
+        TokenType::BoolLiteral => Ok(Ast::Literal(VariableValue::Bool(parse_bool(tok)?))),
+        _ => Ok(Ast::Var(tok.label.to_string())),
+    }
+}
+
+// 'if c ; a : b : d' would otherwise only be reported as a trailing token.
+fn check_duplicate_else(ast: &Ast, iterator: &core::slice::Iter<Token>) -> Result<(), TauschError> {
+    let mut last = ast;
+    while let Ast::Coalesce(_, rhs) = last {
+        last = rhs;
+    }
+    if let Ast::If {
+        on_else: Some(_), ..
+    } = last
+        && let Some(tok) = iterator.as_slice().first()
+        && tok.typ == TokenType::IfElse
+    {
+        return Err(TauschError::Parser(ParserError::DuplicateElse {
+            column: tok.column,
+            span: tok.span,
+        }));
+    }
+    Ok(())
+}
+
+fn parse_group(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+    open_tok: &Token,
+) -> Result<Ast, TauschError> {
+    check_depth(options, depth, open_tok)?;
+    let ast = parse_expression(iterator, options, depth)?;
+    check_duplicate_else(&ast, iterator)?;
+    expect_token(
+        iterator,
+        TokenType::ParenClose,
+        "Expected ')' to close the '(' of the expression!".to_string(),
+    )?;
+    Ok(ast)
+}
+
+fn parse_primary(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Ast, TauschError> {
+    match iterator.next() {
+        Some(tok) => match tok.typ {
+            TokenType::IfStart | TokenType::UnlessStart => {
+                parse_if(iterator, options, depth + 1, tok)
+            }
+            TokenType::ParenOpen => {
+                let group = parse_group(iterator, options, depth + 1, tok)?;
+                parse_postfix(iterator, options, depth, group)
+            }
+            _ if is_value_token(tok) => {
+                let mut parts = vec![parse_value_part(iterator, options, depth, tok)?];
+                while let Some(tok) = iterator.as_slice().first()
+                    && is_value_token(tok)
+                {
+                    iterator.next();
+                    parts.push(parse_value_part(iterator, options, depth, tok)?);
+                }
+
+                Ok(if parts.len() == 1 {
+                    parts.remove(0)
+                } else {
+                    Ast::Concat(parts)
+                })
+            }
+            _ => Err(TauschError::Parser(ParserError::UnexpectedToken {
+                expected: "Expected start of an if-statement or variable name!".to_string(),
+                expected_tokens: value_tokens_and(&[
+                    TokenType::IfStart,
+                    TokenType::UnlessStart,
+                    TokenType::ParenOpen,
+                ]),
+                found: tok.label.to_string(),
+                column: tok.column,
+                span: tok.span,
+            })),
+        },
+        None => Err(TauschError::Parser(ParserError::NoTokens)),
+    }
+}
+
+fn parse_expression(
+    iterator: &mut core::slice::Iter<Token>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Ast, TauschError> {
+    let mut ast = parse_primary(iterator, options, depth)?;
+    while let Some(tok) = accept_token(iterator, TokenType::Coalesce) {
+        ast = Ast::Coalesce(
+            Box::new(ast),
+            Box::new(parse_primary(iterator, options, depth)?),
+        );
+        check_depth(options, depth + ast.height(), &tok)?;
+    }
+    Ok(ast)
+}
+
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    /// Fail with [`ParserError::NoTokens`] instead of producing [`VariableValue::Empty`]
+    /// for an input without any tokens.
+    pub reject_empty: bool,
+    /// Maximum number of nested if-statements and parentheses, else-if chains included.
+    /// Chains like `a && b && c` or `a + b + c` nest once per operator.
+    pub max_depth: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            reject_empty: false,
+            max_depth: 128,
+        }
+    }
+}
+
+/// Parses a token stream into an [`Ast`] that can be evaluated many times.
+pub fn parse(tokens: Vec<Token>) -> Result<Ast, TauschError> {
+    parse_with(tokens, &ParseOptions::default())
+}
+
+pub fn parse_with(tokens: Vec<Token>, options: &ParseOptions) -> Result<Ast, TauschError> {
+    parse_tokens(&tokens, options)
+}
+
+fn parse_tokens(tokens: &[Token], options: &ParseOptions) -> Result<Ast, TauschError> {
+    if tokens.is_empty() && !options.reject_empty {
+        return Ok(Ast::Literal(VariableValue::Empty));
+    }
+
+    let mut iter = tokens.iter();
+    let ast = parse_expression(&mut iter, options, 0)?;
+    check_duplicate_else(&ast, &iter)?;
+
+    if let Some(tok) = iter.next() {
+        return Err(TauschError::Parser(ParserError::TrailingToken {
+            found: tok.label.to_string(),
+            column: tok.column,
+            span: tok.span,
+        }));
+    }
+    Ok(ast)
+}
+
+/// Like [`parse`], but instead of stopping at the first error, skips to the next `;` or `:`
+/// and continues, so that every error of `input` is reported.
+pub fn parse_all_errors(input: &str) -> Result<Ast, Vec<TauschError>> {
+    let tokens = tokenize(input).map_err(|err| vec![err])?;
+    let options = ParseOptions::default();
+    let err = match parse_tokens(&tokens, &options) {
+        Ok(ast) => return Ok(ast),
+        Err(err) => err,
+    };
+
+    let mut errors = Vec::new();
+    let mut rest = skip_past_sync_token(&tokens, &err);
+    errors.push(err);
+    while let Some(remaining) = rest
+        && !remaining.is_empty()
+    {
+        let mut iter = remaining.iter();
+        rest = match parse_expression(&mut iter, &options, 0) {
+            Ok(_) => match iter.as_slice().split_first() {
+                Some((tok, after)) if matches!(tok.typ, TokenType::IfEnd | TokenType::IfElse) => {
+                    Some(after)
+                }
+                Some((tok, _)) => {
+                    let err = TauschError::Parser(ParserError::TrailingToken {
+                        found: tok.label.to_string(),
+                        column: tok.column,
+                        span: tok.span,
+                    });
+                    let after = skip_past_sync_token(iter.as_slice(), &err);
+                    errors.push(err);
+                    after
+                }
+                None => None,
+            },
+            Err(err) => {
+                let after = skip_past_sync_token(remaining, &err);
+                errors.push(err);
+                after
+            }
+        };
+    }
+    Err(errors)
+}
+
+// Returns the tokens after the first ';' or ':' at or behind the token that caused `err`.
+fn skip_past_sync_token<'t, 'a>(
+    tokens: &'t [Token<'a>],
+    err: &TauschError,
+) -> Option<&'t [Token<'a>]> {
+    let TauschError::Parser(err) = err else {
+        return None;
+    };
+    let span = err.span()?;
+    let pos = tokens.iter().position(|tok| tok.span == span)?;
+    let sync = tokens[pos..]
+        .iter()
+        .position(|tok| matches!(tok.typ, TokenType::IfEnd | TokenType::IfElse))?;
+    Some(&tokens[pos + sync + 1..])
+}
+
+pub fn eval(variables: &impl VariableSource, input: &str) -> Result<VariableValue, TauschError> {
+    eval_with(&EvalOptions::default(), variables, input)
+}
+
+pub fn eval_with(
+    options: &EvalOptions,
+    variables: &impl VariableSource,
+    input: &str,
+) -> Result<VariableValue, TauschError> {
+    let tokens = eval_tokenizer(options).tokenize_borrowed(input)?;
+    parse_tokens(&tokens, &options.parse)?
+        .eval_with(variables, options)
+        .map_err(|err| locate_variable(err, &tokens))
+}
+
+fn eval_tokenizer(options: &EvalOptions) -> Tokenizer {
+    let tokenizer =
+        Tokenizer::new().with_case_insensitive_keywords(options.case_insensitive_keywords);
+    match options.max_tokens {
+        Some(max_tokens) => tokenizer.with_max_tokens(max_tokens),
+        None => tokenizer,
+    }
+}
+
+/// Like [`eval`], but renders the result, [`VariableValue::Empty`] becomes an empty string.
+pub fn eval_to_string(variables: &impl VariableSource, input: &str) -> Result<String, TauschError> {
+    Ok(eval(variables, input)?.to_string())
+}
+
+/// An expression that is parsed once and can be evaluated many times.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    ast: Ast,
+    // The span of the first reference to every variable, to point errors at the variable
+    // that caused them.
+    variables: Vec<(String, Span)>,
+}
+
+impl Template {
+    pub fn compile(input: &str) -> Result<Template, TauschError> {
+        Template::compile_with(input, &ParseOptions::default())
+    }
+
+    pub fn compile_with(input: &str, options: &ParseOptions) -> Result<Template, TauschError> {
+        let tokens = Tokenizer::new().tokenize_borrowed(input)?;
+        let ast = parse_tokens(&tokens, options)?;
+        let mut variables: Vec<(String, Span)> = Vec::new();
+        for tok in tokens.iter().filter(|tok| tok.typ == TokenType::Variable) {
+            if !variables.iter().any(|(name, _)| *name == tok.label) {
+                variables.push((tok.label.to_string(), tok.span));
+            }
+        }
+        Ok(Template { ast, variables })
+    }
+
+    pub fn ast(&self) -> &Ast {
+        &self.ast
+    }
+
+    pub fn render(&self, variables: &impl VariableSource) -> Result<VariableValue, TauschError> {
+        self.render_with(variables, &EvalOptions::default())
+    }
+
+    /// Like [`Template::render`], only the options that affect evaluation are used.
+    pub fn render_with(
+        &self,
+        variables: &impl VariableSource,
+        options: &EvalOptions,
+    ) -> Result<VariableValue, TauschError> {
+        self.ast
+            .eval_with(variables, options)
+            .map_err(|err| match err {
+                TauschError::Parser(err) => TauschError::Parser(err.locate_variable(|name| {
+                    self.variables
+                        .iter()
+                        .find(|(var, _)| var == name)
+                        .map(|(_, span)| *span)
+                })),
+                err => err,
+            })
+    }
+}
+
+/// Substitutes every `{{ expr }}` in `text` by the result of evaluating `expr`, the text
+/// around them is kept as is.
+///
+/// A `}}` inside of a string literal does not end the expression.
+pub fn render(variables: &impl VariableSource, text: &str) -> Result<String, TauschError> {
+    render_with(&EvalOptions::default(), variables, text)
+}
+
+pub fn render_with(
+    options: &EvalOptions,
+    variables: &impl VariableSource,
+    text: &str,
+) -> Result<String, TauschError> {
+    let tokenizer = eval_tokenizer(options);
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find("{{") {
+        out.push_str(&rest[..open]);
+        let expr = &rest[open + 2..];
+        let Some(close) = find_expression_end(expr) else {
+            let start = text.len() - rest.len() + open;
+            return Err(TauschError::Tokenizer {
+                message: format!("Unterminated '{{{{' at column {}", column_at(text, start)),
+                span: Some(Span {
+                    start,
+                    end: start + 2,
+                }),
+            });
+        };
+        // Tokenize the expression in place, so that errors point into `text`.
+        let expr_start = text.len() - expr.len();
+        let tokens = tokenizer
+            .token_iter_at(&text[..expr_start + close], expr_start)
+            .collect::<Result<Vec<_>, _>>()?;
+        let val = parse_tokens(&tokens, &options.parse)?
+            .eval_with(variables, options)
+            .map_err(|err| locate_variable(err, &tokens))?;
+        out.push_str(&val.to_string());
+        rest = &expr[close + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn find_expression_end(expr: &str) -> Option<usize> {
+    let mut in_string = false;
+    let mut chars = expr.char_indices();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '"' => in_string = !in_string,
+            '\\' if in_string => {
+                chars.next();
+            }
+            '}' if !in_string && expr[offset..].starts_with("}}") => return Some(offset),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Like [`eval`], but for input that has not been validated as UTF-8 yet.
+pub fn eval_bytes(
+    variables: &impl VariableSource,
+    input: &[u8],
+) -> Result<VariableValue, TauschError> {
+    let input = core::str::from_utf8(input).map_err(|err| TauschError::Tokenizer {
+        message: format!("Invalid UTF-8 at byte {}", err.valid_up_to()),
+        // A truncated sequence at the end of the input has no error length.
+        span: Some(Span {
+            start: err.valid_up_to(),
+            end: err.valid_up_to() + err.error_len().unwrap_or(input.len() - err.valid_up_to()),
+        }),
+    })?;
+    eval(variables, input)
+}
+
+/// Like [`eval`], but returns `None` instead of [`VariableValue::Empty`].
+pub fn eval_opt(
+    variables: &impl VariableSource,
+    input: &str,
+) -> Result<Option<VariableValue>, TauschError> {
+    Ok(match eval(variables, input)? {
+        VariableValue::Empty => None,
+        val => Some(val),
+    })
+}
+
+pub fn eval_resolved(
+    variables: &impl VariableSource,
+    input: &str,
+) -> Result<(Option<String>, VariableValue), TauschError> {
+    let tokens = Tokenizer::new().tokenize_borrowed(input)?;
+    parse_tokens(&tokens, &ParseOptions::default())?
+        .eval_resolved(variables)
+        .map_err(|err| locate_variable(err, &tokens))
+}
+
+/// Like [`eval`], but borrows the result from `variables` instead of cloning it if the result
+/// is the value of a variable.
+#[cfg(feature = "std")]
+pub fn eval_ref<'a>(
+    variables: &'a HashMap<String, VariableValue>,
+    input: &str,
+) -> Result<ValueRef<'a>, TauschError> {
+    let tokens = Tokenizer::new().tokenize_borrowed(input)?;
+    parse_tokens(&tokens, &ParseOptions::default())?
+        .eval_ref(variables)
+        .map_err(|err| locate_variable(err, &tokens))
+}
+
+pub fn eval_traced(
+    variables: &impl VariableSource,
+    input: &str,
+) -> Result<(VariableValue, BranchTaken), TauschError> {
+    let tokens = Tokenizer::new().tokenize_borrowed(input)?;
+    parse_tokens(&tokens, &ParseOptions::default())?
+        .eval_traced(variables)
+        .map_err(|err| locate_variable(err, &tokens))
+}
+
+/// Like [`eval`], but string variables starting with `sigil` are templates themselves: the
+/// rest of the string is evaluated against the same variables and replaces the variable.
+///
+/// A template is only evaluated when its variable is looked up, so templates in branches
+/// that are not taken can't fail. Variables that (indirectly) reference themselves are an
+/// error.
+pub fn eval_recursive(
+    variables: &impl VariableSource,
+    input: &str,
+    sigil: &str,
+) -> Result<VariableValue, TauschError> {
+    let tokens = Tokenizer::new().tokenize_borrowed(input)?;
+    let ast = parse_tokens(&tokens, &ParseOptions::default())?;
+    let source = RecursiveVariables {
+        variables,
+        sigil,
+        stack: RefCell::new(Vec::new()),
+        resolved: RefCell::new(BTreeMap::new()),
+        error: RefCell::new(None),
+    };
+    let result = ast.eval(&source);
+    // A failing template is reported as undefined by `get`, return the actual error instead.
+    if let Some(err) = source.error.into_inner() {
+        return Err(err);
+    }
+    result.map_err(|err| locate_variable(err, &tokens))
+}
+
+// Evaluates templates on demand for `eval_recursive`.
+struct RecursiveVariables<'v, V> {
+    variables: &'v V,
+    sigil: &'v str,
+    // The variables whose templates are being evaluated, to detect cycles.
+    stack: RefCell<Vec<String>>,
+    resolved: RefCell<BTreeMap<String, VariableValue>>,
+    // The first error of a template.
+    error: RefCell<Option<TauschError>>,
+}
+
+impl<V: VariableSource> RecursiveVariables<'_, V> {
+    fn resolve(&self, name: &str, template: &str) -> Result<VariableValue, TauschError> {
+        let stack = self.stack.borrow();
+        if let Some(start) = stack.iter().position(|entry| entry == name) {
+            let mut chain = stack[start..].to_vec();
+            chain.push(name.to_string());
+            return Err(TauschError::Parser(ParserError::RecursiveVariable {
+                chain,
+            }));
+        }
+        drop(stack);
+
+        let ast = parse(tokenize(template)?)?;
+        self.stack.borrow_mut().push(name.to_string());
+        let result = ast.eval(self);
+        self.stack.borrow_mut().pop();
+        result
+    }
+}
+
+impl<V: VariableSource> VariableSource for RecursiveVariables<'_, V> {
+    fn get(&self, name: &str) -> Option<VariableValue> {
+        if let Some(value) = self.resolved.borrow().get(name) {
+            return Some(value.clone());
+        }
+        let value = self.variables.get(name)?;
+        let template = match &value {
+            VariableValue::Str(val) => val.strip_prefix(self.sigil),
+            _ => None,
+        };
+        let Some(template) = template else {
+            return Some(value);
+        };
+        match self.resolve(name, template) {
+            Ok(value) => {
+                self.resolved
+                    .borrow_mut()
+                    .insert(name.to_string(), value.clone());
+                Some(value)
+            }
+            Err(err) => {
+                self.error.borrow_mut().get_or_insert(err);
+                None
+            }
+        }
+    }
+}
+
+/// Checks that `input` is syntactically valid without looking up any variables.
+///
+/// Literals in places that require a bool or a number are rejected by the parser and
+/// therefore reported as well.
+pub fn validate(input: &str) -> Result<(), TauschError> {
+    parse(tokenize(input)?)?;
+    Ok(())
+}
+
+/// Lists the variables `input` references, see [`Ast::referenced_variables`].
+pub fn referenced_variables(input: &str) -> Result<Vec<String>, TauschError> {
+    Ok(parse(tokenize(input)?)?.referenced_variables())
+}
+
+/// Like [`eval`], but also reports non-fatal findings such as unreachable branches or
+/// variables that can't be referenced because their name is a reserved word.
+pub fn eval_with_diagnostics(
+    variables: &impl VariableSource,
+    input: &str,
+) -> (Result<VariableValue, TauschError>, Vec<Diagnostic>) {
+    let tokenizer = Tokenizer::new();
+    let mut diagnostics: Vec<Diagnostic> = tokenizer
+        .reserved_tokens
+        .iter()
+        .filter(|tok| variables.get(&tok.label).is_some())
+        .map(|tok| Diagnostic {
+            message: format!("Variable '{}' is shadowed by a reserved word", tok.label),
+            span: None,
+        })
+        .collect();
+
+    let tokens = match tokenizer.tokenize_borrowed(input) {
+        Ok(tokens) => tokens,
+        Err(err) => return (Err(err), diagnostics),
+    };
+    let ast = match parse_tokens(&tokens, &ParseOptions::default()) {
+        Ok(ast) => ast,
+        Err(err) => return (Err(err), diagnostics),
+    };
+    ast.collect_diagnostics(&mut diagnostics);
+    let result = ast
+        .eval(variables)
+        .map_err(|err| locate_variable(err, &tokens));
+    (result, diagnostics)
+}
+
+pub fn eval_with_tokenizer(
+    tokenizer: &Tokenizer,
+    variables: &impl VariableSource,
+    input: &str,
+) -> Result<VariableValue, TauschError> {
+    let tokens = tokenizer.tokenize_borrowed(input)?;
+    parse_tokens(&tokens, &ParseOptions::default())?
+        .eval(variables)
+        .map_err(|err| locate_variable(err, &tokens))
+}
+
+/// Evaluates tokens created by [`Tokenizer::tokenize_interned`].
+pub fn eval_interned(
+    variables: &impl VariableSource,
+    tokens: &[InternedToken],
+    interner: &StringInterner,
+) -> Result<VariableValue, TauschError> {
+    let tokens: Vec<Token> = tokens
+        .iter()
+        .map(|tok| Token {
+            typ: tok.typ,
+            label: Cow::Borrowed(interner.resolve(tok.label)),
+            span: tok.span,
+            column: tok.column,
+            leading_ws: Cow::Borrowed(""),
+            trailing_ws: Cow::Borrowed(""),
+            escaped_source: None,
+        })
+        .collect();
+    parse_tokens(&tokens, &ParseOptions::default())?
+        .eval(variables)
+        .map_err(|err| locate_variable(err, &tokens))
+}
+
+fn locate_variable(err: TauschError, tokens: &[Token]) -> TauschError {
+    match err {
+        TauschError::Parser(err) => TauschError::Parser(err.locate_variable(|name| {
+            tokens
+                .iter()
+                .find(|tok| tok.typ == TokenType::Variable && tok.label == name)
+                .map(|tok| tok.span)
+        })),
+        err => err,
+    }
+}
+
+/// Evaluates every non-blank line of `input` as its own statement.
+///
+/// Errors are prefixed with the index (starting at 0) of the failing statement. Columns and
+/// spans of errors are relative to the statement.
+pub fn eval_all(
+    variables: &impl VariableSource,
+    input: &str,
+) -> Result<Vec<VariableValue>, TauschError> {
+    eval_all_with(&EvalOptions::default(), variables, input)
+}
+
+pub fn eval_all_with(
+    options: &EvalOptions,
+    variables: &impl VariableSource,
+    input: &str,
+) -> Result<Vec<VariableValue>, TauschError> {
+    eval_lines_with(options, variables, input).collect()
+}
+
+/// Like [`eval_all`], but evaluates the statements lazily, one per call to `next`.
+pub fn eval_lines<'a>(
+    variables: &'a impl VariableSource,
+    input: &'a str,
+) -> impl Iterator<Item = Result<VariableValue, TauschError>> + 'a {
+    input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(index, statement)| statement_result(index, eval(variables, statement)))
+}
+
+pub fn eval_lines_with<'a>(
+    options: &'a EvalOptions,
+    variables: &'a impl VariableSource,
+    input: &'a str,
+) -> impl Iterator<Item = Result<VariableValue, TauschError>> + 'a {
+    input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(index, statement)| statement_result(index, eval_with(options, variables, statement)))
+}
+
+// Prefixes the error of a failing statement with its index.
+fn statement_result(
+    index: usize,
+    result: Result<VariableValue, TauschError>,
+) -> Result<VariableValue, TauschError> {
+    result.map_err(|err| match err {
+        TauschError::Tokenizer { message, span } => TauschError::Tokenizer {
+            message: format!("Statement {index}: {message}"),
+            span,
+        },
+        TauschError::Parser(err) => TauschError::Parser(ParserError::InStatement {
+            index,
+            error: Box::new(err),
+        }),
+    })
+}
+
+/// Formats `err` for display, preceded by the offending line of `input` and a `^` underline
+/// at the span of the error if it has one.
+pub fn render_error(input: &str, err: &TauschError) -> String {
+    let span = match err {
+        TauschError::Parser(err) => err.span(),
+        TauschError::Tokenizer { span, .. } => *span,
+    };
+    // The span may belong to another string, e.g. a single statement of `input`.
+    let Some(span) = span.filter(|span| input.is_char_boundary(span.start)) else {
+        return err.to_string();
+    };
+
+    let line_start = input[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = input[span.start..]
+        .find('\n')
+        .map_or(input.len(), |i| span.start + i);
+    let end = span.end.clamp(span.start, line_end);
+    if !input.is_char_boundary(end) {
+        return err.to_string();
+    }
+    let line = input[line_start..line_end].trim_end_matches('\r');
+    let indent = input[line_start..span.start].chars().count();
+    let width = input[span.start..end].chars().count().max(1);
+    format!("{line}\n{}{}\n{err}", " ".repeat(indent), "^".repeat(width))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::{
+        borrow::Cow,
+        collections::{BTreeMap, HashMap, HashSet},
+    };
+
+    use crate::{
+        Ast, BranchTaken, Condition, Diagnostic, EvalOptions, FunctionRegistry, ParseOptions,
+        ParserError, Span, StringInterner, TauschError, Template, Token, TokenType, Tokenizer,
+        ValueRef, VariableValue, eval, eval_all, eval_all_with, eval_bytes, eval_interned,
+        eval_lines, eval_lines_with, eval_opt, eval_recursive, eval_ref, eval_resolved,
+        eval_to_string, eval_traced, eval_with, eval_with_diagnostics, eval_with_tokenizer,
+        expect_token, parse, parse_all_errors, parse_with, referenced_variables, render,
+        render_error, render_with, reserved_token, tokenize, tokens_to_string, validate,
+    };
+
+    fn token_stream(input: &str) -> Vec<(TokenType, String)> {
+        Tokenizer::new()
+            .tokenize_borrowed(input)
+            .expect("should never fail")
+            .into_iter()
+            .map(|tok| (tok.typ, tok.label.into_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn eval_var() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var = VariableValue::Str("42".to_string());
+        let var_str = "hello".to_string();
+        vars.insert(var_str.clone(), var.clone());
+
+        assert_eq!(eval(&vars, &var_str).expect("should never fail"), var);
+    }
+
+    #[test]
+    fn eval_if_1() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var = VariableValue::Str("42".to_string());
+        vars.insert("hello".to_string(), var.clone());
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+
+        assert_eq!(
+            eval(&vars, "if cond ; hello").expect("should never fail"),
+            var
+        );
+    }
+
+    #[test]
+    fn eval_if_2() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var = VariableValue::Str("42".to_string());
+        vars.insert("hello".to_string(), var.clone());
+        vars.insert("cond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if cond ; hello").expect("should never fail"),
+            VariableValue::Empty
+        );
+    }
+
+    #[test]
+    fn eval_if_else_1() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_hello = VariableValue::Str("42".to_string());
+        vars.insert("hello".to_string(), var_hello.clone());
+        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if cond ; hello : world").expect("should never fail"),
+            var_hello
+        );
+    }
+
+    #[test]
     fn eval_if_else_2() {
         let mut vars = HashMap::<String, VariableValue>::new();
-        let var_world = VariableValue::Str("69".to_string());
-        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
-        vars.insert("world".to_string(), var_world.clone());
+        let var_world = VariableValue::Str("69".to_string());
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("world".to_string(), var_world.clone());
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if ncond ; hello : world").expect("should never fail"),
+            var_world
+        );
+    }
+
+    #[test]
+    fn eval_if_negate() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_hello = VariableValue::Str("42".to_string());
+        vars.insert("hello".to_string(), var_hello.clone());
+        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if ! ncond ; hello : world").expect("should never fail"),
+            var_hello
+        );
+    }
+
+    #[test]
+    fn eval_if_double_negate() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+
+        assert!(eval(&vars, "if ! ! cond ; hello : world").is_err());
+    }
+
+    #[test]
+    fn tokenize_separators_without_spaces() {
+        assert_eq!(
+            token_stream("if cond;hello:world"),
+            vec![
+                (TokenType::IfStart, "if".to_string()),
+                (TokenType::Variable, "cond".to_string()),
+                (TokenType::IfEnd, ";".to_string()),
+                (TokenType::Variable, "hello".to_string()),
+                (TokenType::IfElse, ":".to_string()),
+                (TokenType::Variable, "world".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_negate_without_spaces() {
+        assert_eq!(
+            token_stream("if !cond;a:b"),
+            vec![
+                (TokenType::IfStart, "if".to_string()),
+                (TokenType::IfNegate, "!".to_string()),
+                (TokenType::Variable, "cond".to_string()),
+                (TokenType::IfEnd, ";".to_string()),
+                (TokenType::Variable, "a".to_string()),
+                (TokenType::IfElse, ":".to_string()),
+                (TokenType::Variable, "b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn eval_nested_if_in_true() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_world = VariableValue::Str("69".to_string());
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("world".to_string(), var_world.clone());
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if cond ; if ncond ; hello : world : hello").expect("should never fail"),
+            var_world
+        );
+    }
+
+    #[test]
+    fn eval_nested_if_in_else() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_hello = VariableValue::Str("42".to_string());
+        vars.insert("hello".to_string(), var_hello.clone());
+        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if ncond ; world : if cond ; hello : world").expect("should never fail"),
+            var_hello
+        );
+    }
+
+    #[test]
+    fn eval_nested_if_three_levels() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_hello = VariableValue::Str("42".to_string());
+        vars.insert("hello".to_string(), var_hello.clone());
+        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(
+                &vars,
+                "if cond ; if cond ; if ncond ; world : hello : world : world"
+            )
+            .expect("should never fail"),
+            var_hello
+        );
+    }
+
+    #[test]
+    fn eval_nested_if_untaken_condition_is_not_resolved() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_hello = VariableValue::Str("42".to_string());
+        vars.insert("hello".to_string(), var_hello.clone());
+        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+
+        assert_eq!(
+            eval(&vars, "if cond ; hello : if missing ; hello : world").expect("should never fail"),
+            var_hello
+        );
+    }
+
+    #[test]
+    fn eval_int_literal() {
+        let vars = HashMap::<String, VariableValue>::new();
+
+        assert_eq!(
+            eval(&vars, "42").expect("should never fail"),
+            VariableValue::Int(42)
+        );
+        assert_eq!(
+            eval(&vars, "-9223372036854775808").expect("should never fail"),
+            VariableValue::Int(i64::MIN)
+        );
+    }
+
+    #[test]
+    fn tokenize_int_overflow() {
+        let toker = Tokenizer::new();
+
+        assert!(matches!(
+            toker.tokenize("9223372036854775808".to_string()),
+            Err(TauschError::Tokenizer { .. })
+        ));
+        assert!(matches!(
+            toker.tokenize("12abc".to_string()),
+            Err(TauschError::Tokenizer { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_if_else_ast() {
+        let tokens = Tokenizer::new()
+            .tokenize("if !cond ; hello : world".to_string())
+            .expect("should never fail");
+
+        assert_eq!(
+            parse(tokens).expect("should never fail"),
+            Ast::If {
+                cond: Condition::Not(Box::new(Condition::Var("cond".to_string()))),
+                on_true: Box::new(Ast::Var("hello".to_string())),
+                on_else: Some(Box::new(Ast::Var("world".to_string()))),
+            }
+        );
+    }
+
+    #[test]
+    fn ast_eval_many() {
+        let tokens = Tokenizer::new()
+            .tokenize("if cond ; hello : world".to_string())
+            .expect("should never fail");
+        let ast = parse(tokens).expect("should never fail");
+
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
+
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        assert_eq!(
+            ast.eval(&vars).expect("should never fail"),
+            VariableValue::Str("42".to_string())
+        );
+
+        vars.insert("cond".to_string(), VariableValue::Bool(false));
+        assert_eq!(
+            ast.eval(&vars).expect("should never fail"),
+            VariableValue::Str("69".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_if_and_or() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_hello = VariableValue::Str("42".to_string());
+        let var_world = VariableValue::Str("69".to_string());
+        vars.insert("hello".to_string(), var_hello.clone());
+        vars.insert("world".to_string(), var_world.clone());
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if cond && ncond ; hello : world").expect("should never fail"),
+            var_world
+        );
+        assert_eq!(
+            eval(&vars, "if cond&&!ncond ; hello : world").expect("should never fail"),
+            var_hello
+        );
+        assert_eq!(
+            eval(&vars, "if ncond || cond ; hello : world").expect("should never fail"),
+            var_hello
+        );
+        assert_eq!(
+            eval(&vars, "if ncond || ncond && cond ; hello : world").expect("should never fail"),
+            var_world
+        );
+    }
+
+    #[test]
+    fn eval_if_and_or_short_circuit() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_hello = VariableValue::Str("42".to_string());
+        let var_world = VariableValue::Str("69".to_string());
+        vars.insert("hello".to_string(), var_hello.clone());
+        vars.insert("world".to_string(), var_world.clone());
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if ncond && missing ; hello : world").expect("should never fail"),
+            var_world
+        );
+        assert_eq!(
+            eval(&vars, "if cond || missing ; hello : world").expect("should never fail"),
+            var_hello
+        );
+        assert!(eval(&vars, "if cond && missing ; hello : world").is_err());
+    }
+
+    #[test]
+    fn eval_if_and_or_type_error() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert!(eval(&vars, "if cond && hello ; hello : world").is_err());
+        assert!(eval(&vars, "if ncond || hello ; hello : world").is_err());
+    }
+
+    #[test]
+    fn tokenize_spans() {
+        let tokens = Tokenizer::new()
+            .tokenize("if cond;\n  héllo".to_string())
+            .expect("should never fail");
+        let spans: Vec<((usize, usize), usize)> = tokens
+            .iter()
+            .map(|tok| ((tok.span.start, tok.span.end), tok.column))
+            .collect();
+
+        assert_eq!(
+            spans,
+            vec![((0, 2), 1), ((3, 7), 4), ((7, 8), 8), ((11, 17), 3)]
+        );
+    }
+
+    #[test]
+    fn token_iter_matches_tokenize() {
+        let input = "if a && \"x\\\"y\" ;\n  héllo : -4.2 # comment";
+        let tokenizer = Tokenizer::new();
+        let describe = |tok: &crate::Token| (tok.typ, tok.label.to_string(), tok.span, tok.column);
+
+        let lazy: Vec<_> = tokenizer
+            .token_iter(input)
+            .map(|tok| describe(&tok.expect("should never fail")))
+            .collect();
+        let eager: Vec<_> = tokenize(input)
+            .expect("should never fail")
+            .iter()
+            .map(describe)
+            .collect();
+        assert_eq!(lazy, eager);
+
+        let mut iter = tokenizer.token_iter("hello ? world");
+        assert!(iter.next().is_some_and(|tok| tok.is_ok()));
+        assert!(iter.next().is_some_and(|tok| tok.is_err()));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn tokenize_unicode_var_names() {
+        assert_eq!(
+            token_stream("café переменная _x cafe\u{301} 名前"),
+            vec![
+                (TokenType::Variable, "café".to_string()),
+                (TokenType::Variable, "переменная".to_string()),
+                (TokenType::Variable, "_x".to_string()),
+                (TokenType::Variable, "cafe\u{301}".to_string()),
+                (TokenType::Variable, "名前".to_string()),
+            ]
+        );
+
+        let tokenizer = Tokenizer::new();
+        assert!(tokenizer.tokenize("\u{301}abc".to_string()).is_err());
+        assert!(tokenizer.tokenize("1abc".to_string()).is_err());
+        assert!(tokenizer.tokenize("²".to_string()).is_err());
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let input = "  if  cond&&!x;\n\t\"a \\\"b\\\\\" # note\n :  -1.5  ";
+        let tokens = Tokenizer::new()
+            .with_trivia(true)
+            .tokenize_borrowed(input)
+            .expect("should never fail");
+        assert_eq!(tokens[0].leading_ws, "  ");
+        assert_eq!(tokens[1].leading_ws, "  ");
+        assert_eq!(tokens[2].leading_ws, "");
+        assert_eq!(tokens.last().expect("should never fail").trailing_ws, "  ");
+        assert_eq!(tokens_to_string(&tokens), input);
+
+        // Escapes of variables are kept as written, '\\if' must not turn into a keyword.
+        for input in ["\\if ; a", "a\\b \\!x", "if c ; x\\ y : \\if"] {
+            let tokens = Tokenizer::new()
+                .with_trivia(true)
+                .tokenize_borrowed(input)
+                .expect("should never fail");
+            assert_eq!(tokens_to_string(&tokens), input);
+        }
+        let tokens = Tokenizer::new()
+            .with_trivia(true)
+            .tokenize_borrowed("\\if a\\b c")
+            .expect("should never fail");
+        assert_eq!(tokens[0].escaped_source.as_deref(), Some("\\if"));
+        assert_eq!(tokens[1].escaped_source.as_deref(), Some("a\\b"));
+        assert_eq!(tokens[2].escaped_source, None);
+
+        let tokens = Tokenizer::new()
+            .tokenize_borrowed(input)
+            .expect("should never fail");
+        assert!(tokens.iter().all(|tok| tok.leading_ws.is_empty()));
+    }
+
+    #[test]
+    fn tokenize_escaped_var_names() {
+        assert_eq!(
+            token_stream("a\\:b if\\; \\!x x\\\\ y\\ z"),
+            vec![
+                (TokenType::Variable, "a:b".to_string()),
+                (TokenType::Variable, "if;".to_string()),
+                (TokenType::Variable, "!x".to_string()),
+                (TokenType::Variable, "x\\".to_string()),
+                (TokenType::Variable, "y z".to_string()),
+            ]
+        );
+
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("a:b".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        assert_eq!(
+            eval(&vars, "if cond ; a\\:b : cond").expect("should never fail"),
+            VariableValue::Str("42".to_string())
+        );
+
+        let Err(TauschError::Tokenizer { message: err, .. }) = tokenize("hello\\") else {
+            panic!("expected a tokenizer error");
+        };
+        assert_eq!(err, "Dangling '\\' at column 6");
+
+        let input = "a\\:b \\!x";
+        let tokens = Tokenizer::new()
+            .with_trivia(true)
+            .tokenize_borrowed(input)
+            .expect("should never fail");
+        assert_eq!(tokens_to_string(&tokens), input);
+    }
+
+    #[test]
+    fn tokenize_case_insensitive_keywords() {
+        let vars = crate::vars! { "cond" => true, "a" => "42", "IF" => "shouted" };
+        let insensitive = Tokenizer::new().with_case_insensitive_keywords(true);
+
+        let tokens = insensitive
+            .tokenize("IF Cond ; a".to_string())
+            .expect("should never fail");
+        assert_eq!(tokens[0].typ, TokenType::IfStart);
+        assert_eq!(tokens[0].label, "IF");
+        assert_eq!(tokens[1].typ, TokenType::Variable);
+        assert_eq!(tokens[1].label, "Cond");
+        assert_eq!(
+            eval_with_tokenizer(&insensitive, &vars, "If cond ; a : TRUE")
+                .expect("should never fail"),
+            VariableValue::Str("42".to_string())
+        );
+        assert_eq!(
+            eval_with_tokenizer(&insensitive, &vars, "FALSE").expect("should never fail"),
+            VariableValue::Bool(false)
+        );
+
+        assert_eq!(
+            token_stream("IF cond ; a")[0],
+            (TokenType::Variable, "IF".to_string())
+        );
+        assert_eq!(
+            eval(&vars, "IF").expect("should never fail"),
+            VariableValue::Str("shouted".to_string())
+        );
+    }
+
+    #[test]
+    fn token_new_and_display() {
+        let tok = Token::new(TokenType::Variable, "hello");
+        assert_eq!(tok.typ, TokenType::Variable);
+        assert_eq!(tok.label, "hello");
+        assert_eq!(tok.to_string(), "Variable(\"hello\")");
+        assert_eq!(
+            Token::new(TokenType::IfStart, "if".to_string()).to_string(),
+            "IfStart(\"if\")"
+        );
+        assert_eq!(
+            tokenize("\"a\\\"b\"").expect("should never fail")[0].to_string(),
+            "StrLiteral(\"a\\\"b\")"
+        );
+
+        let toker = Tokenizer::with_tokens(vec![
+            Token::new(TokenType::IfStart, "when"),
+            Token::new(TokenType::IfEnd, "then"),
+            Token::new(TokenType::IfElse, "else"),
+        ])
+        .expect("should never fail");
+        let tokens = toker
+            .tokenize("when a then b else c".to_string())
+            .expect("should never fail");
+        assert_eq!(tokens[4].typ, TokenType::IfElse);
+    }
+
+    #[test]
+    fn tokenize_error_column() {
+        let Err(TauschError::Tokenizer { message: err, .. }) =
+            Tokenizer::new().tokenize("if cond ? hello".to_string())
+        else {
+            panic!("expected a tokenizer error");
+        };
+
+        assert_eq!(err, "Unknown token: '?' at column 9");
+    }
+
+    #[test]
+    fn parse_error_column() {
+        let vars = HashMap::<String, VariableValue>::new();
+        let Err(TauschError::Parser(err)) = eval(&vars, "if cond : hello") else {
+            panic!("expected a parser error");
+        };
+
+        assert!(err.to_string().ends_with("(at column 9)"));
+    }
+
+    #[test]
+    fn eval_str_literal() {
+        let vars = HashMap::<String, VariableValue>::new();
+
+        assert_eq!(
+            eval(&vars, "\"hello world\"").expect("should never fail"),
+            VariableValue::Str("hello world".to_string())
+        );
+        assert_eq!(
+            eval(&vars, "\"\"").expect("should never fail"),
+            VariableValue::Str("".to_string())
+        );
+        assert_eq!(
+            eval(&vars, r#""say \"hi\" \\ bye""#).expect("should never fail"),
+            VariableValue::Str(r#"say "hi" \ bye"#.to_string())
+        );
+    }
+
+    #[test]
+    fn tokenize_unterminated_str_literal() {
+        let toker = Tokenizer::new();
+
+        assert!(matches!(
+            toker.tokenize("\"hello".to_string()),
+            Err(TauschError::Tokenizer { .. })
+        ));
+        assert!(matches!(
+            toker.tokenize(r#""hello\""#.to_string()),
+            Err(TauschError::Tokenizer { .. })
+        ));
+        assert_eq!(
+            tokenize("\"abc"),
+            Err(TauschError::Tokenizer {
+                message: "Unterminated string literal starting at column 1".to_string(),
+                span: Some(Span { start: 0, end: 1 }),
+            })
+        );
+        assert_eq!(
+            tokenize("hello\n  world \"a\nb"),
+            Err(TauschError::Tokenizer {
+                message: "Unterminated string literal starting at column 9".to_string(),
+                span: Some(Span { start: 14, end: 15 }),
+            })
+        );
+    }
+
+    #[test]
+    fn eval_if_eq() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_yes = VariableValue::Str("yes".to_string());
+        let var_no = VariableValue::Str("no".to_string());
+        vars.insert("yes".to_string(), var_yes.clone());
+        vars.insert("no".to_string(), var_no.clone());
+
+        let values = [
+            ("b1", VariableValue::Bool(true)),
+            ("b2", VariableValue::Bool(false)),
+            ("s1", VariableValue::Str("42".to_string())),
+            ("s2", VariableValue::Str("69".to_string())),
+            ("i1", VariableValue::Int(42)),
+            ("i2", VariableValue::Int(69)),
+            ("e", VariableValue::Empty),
+        ];
+        for (name, val) in values.iter() {
+            vars.insert(name.to_string(), val.clone());
+        }
+
+        for (lhs, val_lhs) in values.iter() {
+            for (rhs, val_rhs) in values.iter() {
+                let (on_eq, on_ne) = if val_lhs == val_rhs {
+                    (&var_yes, &var_no)
+                } else {
+                    (&var_no, &var_yes)
+                };
+                assert_eq!(
+                    &eval(&vars, &format!("if {lhs} == {rhs} ; yes : no"))
+                        .expect("should never fail"),
+                    on_eq
+                );
+                assert_eq!(
+                    &eval(&vars, &format!("if {lhs}!={rhs} ; yes : no"))
+                        .expect("should never fail"),
+                    on_ne
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn eval_if_eq_missing_variable() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+
+        assert!(eval(&vars, "if hello == missing ; hello : hello").is_err());
+    }
+
+    #[test]
+    fn eval_all_statements() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_hello = VariableValue::Str("42".to_string());
+        let var_world = VariableValue::Str("69".to_string());
+        vars.insert("hello".to_string(), var_hello.clone());
+        vars.insert("world".to_string(), var_world.clone());
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+
+        assert_eq!(
+            eval_all(&vars, "hello\nworld").expect("should never fail"),
+            vec![var_hello.clone(), var_world.clone()]
+        );
+        assert_eq!(
+            eval_all(&vars, "hello\n\nif cond ; world : hello\n\"!\"\n")
+                .expect("should never fail"),
+            vec![var_hello, var_world, VariableValue::Str("!".to_string())]
+        );
+    }
+
+    #[test]
+    fn eval_all_reports_failing_statement() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+
+        let Err(TauschError::Parser(err)) = eval_all(&vars, "hello\nmissing\nhello") else {
+            panic!("expected a parser error");
+        };
+        assert!(matches!(
+            &err,
+            ParserError::InStatement {
+                index: 1,
+                error,
+            } if matches!(error.as_ref(), ParserError::UndefinedVariable { name, .. } if name == "missing")
+        ));
+        assert!(err.to_string().starts_with("Statement 1: "));
+    }
+
+    #[test]
+    fn tokenize_longest_match() {
+        let toker = Tokenizer::from_tokens(vec![
+            reserved_token(TokenType::IfElse, ":"),
+            reserved_token(TokenType::IfEnd, "::"),
+        ]);
+        let tokens: Vec<(TokenType, String)> = toker
+            .tokenize("a::b:c:::".to_string())
+            .expect("should never fail")
+            .into_iter()
+            .map(|tok| (tok.typ, tok.label.into_owned()))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenType::Variable, "a".to_string()),
+                (TokenType::IfEnd, "::".to_string()),
+                (TokenType::Variable, "b".to_string()),
+                (TokenType::IfElse, ":".to_string()),
+                (TokenType::Variable, "c".to_string()),
+                (TokenType::IfEnd, "::".to_string()),
+                (TokenType::IfElse, ":".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn display_variable_value() {
+        assert_eq!(VariableValue::Bool(true).to_string(), "true");
+        assert_eq!(VariableValue::Bool(false).to_string(), "false");
+        assert_eq!(
+            VariableValue::Str("hello world".to_string()).to_string(),
+            "hello world"
+        );
+        assert_eq!(VariableValue::Int(-42).to_string(), "-42");
+        assert_eq!(VariableValue::Empty.to_string(), "");
+    }
+
+    #[test]
+    fn variable_value_ordering() {
+        use VariableValue::{Bool, Empty, Float, Int, Str};
+
+        assert!(Bool(false) < Bool(true));
+        assert!(Str("abc".to_string()) < Str("abd".to_string()));
+        assert!(Str("B".to_string()) < Str("a".to_string()));
+        assert!(Int(-3) < Int(2));
+        assert!(Float(-0.5) < Float(0.25));
+        assert!(Int(1) < Float(1.5) && Float(1.5) < Int(2));
+        assert!(Float(-1.5) < Int(-1));
+        assert!(Int(1) < Float(1.0));
+        assert!(Int(i64::MAX) < Float(9_223_372_036_854_775_808.0));
+        assert!(Int(9_007_199_254_740_993) > Float(9_007_199_254_740_992.0));
+        assert_eq!(Float(f64::NAN), Float(f64::NAN));
+        assert!(Float(f64::INFINITY) < Float(f64::NAN));
+
+        let mut values = vec![Str("a".to_string()), Float(0.5), Empty, Int(0), Bool(true)];
+        values.sort();
+        assert_eq!(
+            values,
+            vec![Empty, Bool(true), Int(0), Float(0.5), Str("a".to_string())]
+        );
+    }
+
+    #[test]
+    fn eval_custom_tokens() {
+        let toker = Tokenizer::with_tokens(vec![
+            reserved_token(TokenType::IfStart, "when"),
+            reserved_token(TokenType::IfEnd, "then"),
+            reserved_token(TokenType::IfElse, ":"),
+        ])
+        .expect("should never fail");
+
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_world = VariableValue::Str("69".to_string());
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("world".to_string(), var_world.clone());
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval_with_tokenizer(&toker, &vars, "when ncond then hello : world")
+                .expect("should never fail"),
+            var_world
+        );
+        assert!(eval_with_tokenizer(&toker, &vars, "if ncond ; hello : world").is_err());
+    }
+
+    #[test]
+    fn custom_symbol_chars() {
+        let toker = Tokenizer::with_tokens(vec![
+            reserved_token(TokenType::IfStart, "if"),
+            reserved_token(TokenType::IfEnd, "@;"),
+            reserved_token(TokenType::IfElse, ":"),
+        ])
+        .expect("should never fail");
+
+        let tokens = toker
+            .tokenize("if cond @; hello : world".to_string())
+            .expect("should never fail");
+        assert_eq!(tokens[2].typ, TokenType::IfEnd);
+        assert_eq!(tokens[2].label, "@;");
+
+        assert!(toker.tokenize("if cond ; hello".to_string()).is_err());
+        assert!(toker.tokenize("if cond @ hello".to_string()).is_err());
+        assert!(toker.tokenize("if cond $ hello".to_string()).is_err());
+        assert!(toker.tokenize("if cond ! hello".to_string()).is_err());
+    }
+
+    #[test]
+    fn custom_tokens_missing_mandatory() {
+        assert!(matches!(
+            Tokenizer::with_tokens(vec![
+                reserved_token(TokenType::IfStart, "when"),
+                reserved_token(TokenType::IfElse, ":"),
+            ]),
+            Err(TauschError::Tokenizer { .. })
+        ));
+    }
+
+    #[test]
+    fn eval_resolved_key() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_hello = VariableValue::Str("42".to_string());
+        let var_world = VariableValue::Str("69".to_string());
+        vars.insert("hello".to_string(), var_hello.clone());
+        vars.insert("world".to_string(), var_world.clone());
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval_resolved(&vars, "if cond ; hello : world").expect("should never fail"),
+            (Some("hello".to_string()), var_hello)
+        );
+        assert_eq!(
+            eval_resolved(&vars, "if ncond ; hello : world").expect("should never fail"),
+            (Some("world".to_string()), var_world)
+        );
+        assert_eq!(
+            eval_resolved(&vars, "if ncond ; hello").expect("should never fail"),
+            (None, VariableValue::Empty)
+        );
+        assert_eq!(
+            eval_resolved(&vars, "\"lit\"").expect("should never fail"),
+            (None, VariableValue::Str("lit".to_string()))
+        );
+    }
+
+    #[test]
+    fn eval_else_if_chain() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        for (name, val) in [("a", "1"), ("b", "2"), ("c", "3"), ("d", "4")] {
+            vars.insert(name.to_string(), VariableValue::Str(val.to_string()));
+        }
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        let eval_str = |input: &str| eval(&vars, input).expect("should never fail").to_string();
+
+        assert_eq!(eval_str("if cond ; a : cond ; b : c"), "1");
+        assert_eq!(eval_str("if ncond ; a : cond ; b : c"), "2");
+        assert_eq!(eval_str("if ncond ; a : ncond ; b : c"), "3");
+        assert_eq!(
+            eval_str("if ncond ; a : ncond ; b : cond && cond ; c : d"),
+            "3"
+        );
+        assert_eq!(eval_str("if ncond ; a : ncond ; b : !cond ; c : d"), "4");
+        assert_eq!(
+            eval_str("if ncond ; a : ncond ; b : ncond ; c").to_string(),
+            ""
+        );
+    }
+
+    #[test]
+    fn eval_else_if_chain_short_circuit() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+
+        assert_eq!(
+            eval(&vars, "if cond ; hello : missing ; hello : hello").expect("should never fail"),
+            VariableValue::Str("42".to_string())
+        );
+    }
+
+    #[test]
+    fn parser_error_spans() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+
+        let input = "if cond ; héllo_wörld : cond";
+        let Err(TauschError::Parser(err)) = eval(&vars, input) else {
+            panic!("expected a parser error");
+        };
+        let span = err.span().expect("should never fail");
+        assert_eq!(&input[span.start..span.end], "héllo_wörld");
+
+        let input = "if cond hello";
+        let Err(TauschError::Parser(err)) = eval(&vars, input) else {
+            panic!("expected a parser error");
+        };
+        assert_eq!(err.span(), Some(Span { start: 8, end: 13 }));
+
+        let Err(TauschError::Parser(err)) = eval_all(&vars, "cond\ncond ; cond") else {
+            panic!("expected a parser error");
+        };
+        assert_eq!(err.span(), Some(Span { start: 5, end: 6 }));
+    }
+
+    #[test]
+    fn parser_error_variants() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+
+        assert!(matches!(
+            eval(&vars, "missing"),
+            Err(TauschError::Parser(ParserError::UndefinedVariable { name, .. })) if name == "missing"
+        ));
+        assert!(matches!(
+            eval(&vars, "if hello ; hello"),
+            Err(TauschError::Parser(ParserError::TypeMismatch {
+                name,
+                expected: "bool",
+                found: "str",
+                ..
+            })) if name == "hello"
+        ));
+        assert!(matches!(
+            eval(&vars, "if cond : hello"),
+            Err(TauschError::Parser(ParserError::UnexpectedToken { found, column: 9, .. }))
+                if found == ":"
+        ));
+        assert!(matches!(
+            eval(&vars, "if cond ;"),
+            Err(TauschError::Parser(ParserError::UnexpectedEof { .. }))
+        ));
+        assert_eq!(
+            eval(&vars, "if hello ; hello")
+                .map_err(|err| match err {
+                    TauschError::Parser(err) => err.to_string(),
+                    TauschError::Tokenizer { message, .. } => message,
+                })
+                .expect_err("should always fail"),
+            "Variable 'hello' is not a bool!"
+        );
+    }
+
+    #[test]
+    fn eval_if_untaken_branch_is_not_resolved() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        let var_hello = VariableValue::Str("42".to_string());
+        vars.insert("hello".to_string(), var_hello.clone());
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if cond ; hello : missing").expect("should never fail"),
+            var_hello
+        );
+        assert_eq!(
+            eval(&vars, "if ncond ; missing : hello").expect("should never fail"),
+            var_hello
+        );
+        assert_eq!(
+            eval(&vars, "if ncond ; missing").expect("should never fail"),
+            VariableValue::Empty
+        );
+        assert!(eval(&vars, "if cond ; missing : hello").is_err());
+        assert!(eval(&vars, "if ncond ; hello : missing").is_err());
+    }
+
+    #[test]
+    fn parse_trailing_tokens() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+
+        assert!(matches!(
+            eval(&vars, "hello ; world"),
+            Err(TauschError::Parser(ParserError::TrailingToken { found, column: 7, .. }))
+                if found == ";"
+        ));
+        assert!(matches!(
+            eval(&vars, "if cond ; hello world"),
+            Err(TauschError::Parser(ParserError::TrailingToken { found, column: 17, .. }))
+                if found == "world"
+        ));
+        assert!(matches!(
+            eval(&vars, "if cond ; hello : world hello"),
+            Err(TauschError::Parser(ParserError::TrailingToken { found, .. })) if found == "hello"
+        ));
+    }
+
+    #[test]
+    fn tokenize_comments() {
+        assert!(token_stream("# just a comment").is_empty());
+        assert_eq!(
+            token_stream("hello # comment"),
+            vec![(TokenType::Variable, "hello".to_string())]
+        );
+        assert_eq!(
+            token_stream("# comment\nhello"),
+            vec![(TokenType::Variable, "hello".to_string())]
+        );
+        assert_eq!(
+            token_stream("\"# no comment\""),
+            vec![(TokenType::StrLiteral, "# no comment".to_string())]
+        );
+    }
+
+    #[test]
+    fn tokenize_borrowed_large_input() {
+        let input = "if cond ; hello : \"w\\\"orld\" ".repeat(10_000 / 6 + 1);
+        let toker = Tokenizer::new();
+        let tokens = toker.tokenize_borrowed(&input).expect("should never fail");
+
+        assert!(tokens.len() >= 10_000);
+        assert_eq!(
+            tokens,
+            toker.tokenize(input.clone()).expect("should never fail")
+        );
+        for tok in tokens.iter() {
+            match tok.typ {
+                TokenType::StrLiteral => {
+                    assert!(matches!(tok.label, Cow::Owned(_)));
+                    assert_eq!(tok.label, "w\"orld");
+                }
+                _ => {
+                    assert!(matches!(tok.label, Cow::Borrowed(_)));
+                    assert_eq!(tok.label, &input[tok.span.start..tok.span.end]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn eval_coerce_bool_strings() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
+        vars.insert("yes".to_string(), VariableValue::Str("TRUE".to_string()));
+        vars.insert("no".to_string(), VariableValue::Str("false".to_string()));
+        vars.insert("maybe".to_string(), VariableValue::Str("maybe".to_string()));
+
+        let options = EvalOptions {
+            coerce_bool_strings: true,
+            ..EvalOptions::default()
+        };
+        let eval_coerced = |input: &str| {
+            let tokens = Tokenizer::new()
+                .tokenize_borrowed(input)
+                .expect("should never fail");
+            parse(tokens)
+                .expect("should never fail")
+                .eval_with(&vars, &options)
+        };
+
+        assert_eq!(
+            eval_coerced("if yes ; hello : world").expect("should never fail"),
+            VariableValue::Str("42".to_string())
+        );
+        assert_eq!(
+            eval_coerced("if no ; hello : world").expect("should never fail"),
+            VariableValue::Str("69".to_string())
+        );
+        assert!(matches!(
+            eval_coerced("if maybe ; hello : world"),
+            Err(TauschError::Parser(ParserError::TypeMismatch { .. }))
+        ));
+        assert!(eval(&vars, "if yes ; hello : world").is_err());
+    }
+
+    #[test]
+    fn eval_truthiness() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("str".to_string(), VariableValue::Str("x".to_string()));
+        vars.insert("nostr".to_string(), VariableValue::Str(String::new()));
+        vars.insert("int".to_string(), VariableValue::Int(-1));
+        vars.insert("noint".to_string(), VariableValue::Int(0));
+        vars.insert("float".to_string(), VariableValue::Float(0.5));
+        vars.insert("nofloat".to_string(), VariableValue::Float(0.0));
+        vars.insert("bool".to_string(), VariableValue::Bool(true));
+        vars.insert("nobool".to_string(), VariableValue::Bool(false));
+        vars.insert("empty".to_string(), VariableValue::Empty);
+
+        let options = EvalOptions {
+            truthiness: true,
+            ..EvalOptions::default()
+        };
+        let negated = |name: &str| {
+            let input = format!("if !{name} ; 1 : 0");
+            parse(tokenize(&input).expect("should never fail"))
+                .expect("should never fail")
+                .eval_with(&vars, &options)
+                .expect("should never fail")
+        };
+
+        for name in ["str", "int", "float", "bool"] {
+            assert_eq!(negated(name), VariableValue::Int(0), "{name}");
+        }
+        for name in ["nostr", "noint", "nofloat", "nobool", "empty"] {
+            assert_eq!(negated(name), VariableValue::Int(1), "{name}");
+        }
+        assert!(matches!(
+            eval(&vars, "if !empty ; 1 : 0"),
+            Err(TauschError::Parser(ParserError::TypeMismatch { .. }))
+        ));
+    }
+
+    #[test]
+    fn eval_diagnostics() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+
+        let (result, diagnostics) = eval_with_diagnostics(&vars, "if true ; hello : world");
+        assert_eq!(
+            result.expect("should never fail"),
+            VariableValue::Str("42".to_string())
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("Unreachable else-branch"));
+
+        let (result, diagnostics) = eval_with_diagnostics(&vars, "hello");
+        assert!(result.is_ok());
+        assert!(diagnostics.is_empty());
+
+        vars.insert("if".to_string(), VariableValue::Bool(true));
+        let (result, diagnostics) = eval_with_diagnostics(&vars, "if false ; world");
+        assert_eq!(result.expect("should never fail"), VariableValue::Empty);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Variable 'if' is shadowed by a reserved word",
+                "Unreachable if-branch, the condition is always false",
+            ]
+        );
+    }
+
+    #[test]
+    fn eval_many_rows() {
+        let ast = parse(tokenize("if cond ; hello : world").expect("should never fail"))
+            .expect("should never fail");
+        let row = |cond: bool, hello: &str| {
+            let mut vars = HashMap::<String, VariableValue>::new();
+            vars.insert("cond".to_string(), VariableValue::Bool(cond));
+            vars.insert("hello".to_string(), VariableValue::from(hello));
+            vars.insert("world".to_string(), VariableValue::from("69"));
+            vars
+        };
+        let mut results = ast
+            .eval_many(&[row(true, "42"), row(false, "42"), row(true, "1337")])
+            .into_iter();
+
+        assert_eq!(
+            results.next().map(|res| res.expect("should never fail")),
+            Some(VariableValue::from("42"))
+        );
+        assert_eq!(
+            results.next().map(|res| res.expect("should never fail")),
+            Some(VariableValue::from("69"))
+        );
+        assert_eq!(
+            results.next().map(|res| res.expect("should never fail")),
+            Some(VariableValue::from("1337"))
+        );
+        assert!(results.next().is_none());
+    }
+
+    #[test]
+    fn vars_macro() {
+        let mut expected = HashMap::<String, VariableValue>::new();
+        expected.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        expected.insert("cond".to_string(), VariableValue::Bool(true));
+        expected.insert("n".to_string(), VariableValue::Int(7));
+        expected.insert("x".to_string(), VariableValue::Float(0.5));
+
+        assert_eq!(
+            crate::vars! { "hello" => "42", "cond" => true, "n" => 7, "x" => 0.5 },
+            expected
+        );
+        assert_eq!(
+            crate::vars! {
+                "hello" => "42".to_string(),
+                "cond" => true,
+                "n" => 7,
+                "x" => 0.5,
+            },
+            expected
+        );
+        assert!(crate::vars! {}.is_empty());
+    }
+
+    #[test]
+    fn eval_optional() {
+        let vars = crate::vars! { "hello" => "42", "ncond" => false };
+
+        assert_eq!(
+            eval_opt(&vars, "if ncond ; hello").expect("should never fail"),
+            None
+        );
+        assert_eq!(
+            eval_opt(&vars, "if !ncond ; hello").expect("should never fail"),
+            Some(VariableValue::Str("42".to_string()))
+        );
+        assert!(eval_opt(&vars, "missing").is_err());
+    }
+
+    #[test]
+    fn parse_duplicate_else() {
+        for input in [
+            "if c ; a : b : d",
+            "if c ; a : c ; b : d : e",
+            "x ?? if c ; a : b : d",
+            "(if c ; a : b : d)",
+        ] {
+            assert!(
+                matches!(
+                    validate(input),
+                    Err(TauschError::Parser(ParserError::DuplicateElse { .. }))
+                ),
+                "{input}"
+            );
+        }
+        assert!(matches!(
+            validate("if c ; a : b : d"),
+            Err(TauschError::Parser(ParserError::DuplicateElse {
+                column: 14,
+                ..
+            }))
+        ));
+
+        assert!(validate("if c ; if d ; a : b : e").is_ok());
+        assert!(matches!(
+            validate("hello : world"),
+            Err(TauschError::Parser(ParserError::TrailingToken { .. }))
+        ));
+    }
+
+    #[test]
+    fn parse_reports_all_errors() {
+        assert!(parse_all_errors("if a ; b : c").is_ok());
+
+        let errors = parse_all_errors("if a b ; c : if d e ; f").expect_err("should fail");
+        let columns: Vec<usize> = errors
+            .iter()
+            .map(|err| match err {
+                TauschError::Parser(ParserError::UnexpectedToken { column, .. }) => *column,
+                err => panic!("unexpected error {err}"),
+            })
+            .collect();
+        assert_eq!(columns, vec![6, 19]);
+
+        let errors = parse_all_errors("if ; a b c : d ) ; e").expect_err("should fail");
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            errors[1],
+            TauschError::Parser(ParserError::TrailingToken { column: 16, .. })
+        ));
+
+        assert_eq!(
+            parse_all_errors("if a ;").expect_err("should fail").len(),
+            1
+        );
+        assert_eq!(parse_all_errors("\"a").expect_err("should fail").len(), 1);
+    }
+
+    #[test]
+    fn eval_coalesce() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("nothing".to_string(), VariableValue::Empty);
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        let eval_str = |input: &str| eval(&vars, input).expect("should never fail").to_string();
+        assert_eq!(eval_str("nothing ?? hello"), "42");
+        assert_eq!(eval_str("hello ?? missing"), "42");
+        assert_eq!(eval_str("nothing ?? nothing ?? 1"), "1");
+        assert_eq!(eval_str("(if ncond ; hello) ?? \"default\""), "default");
+        assert_eq!(eval_str("(if cond ; hello) ?? \"default\""), "42");
+        assert_eq!(eval_str("if ncond ; hello ?? \"default\""), "default");
+        assert_eq!(eval_str("if cond ; (nothing ?? 2) : 3"), "2");
+        assert_eq!(
+            eval_resolved(&vars, "nothing ?? hello").expect("should never fail"),
+            (
+                Some("hello".to_string()),
+                VariableValue::Str("42".to_string())
+            )
+        );
+
+        assert!(eval(&vars, "nothing ??").is_err());
+        assert!(eval(&vars, "(nothing ?? hello").is_err());
+        assert!(eval(&vars, "nothing ? hello").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("answer".to_string(), VariableValue::Int(42));
+        vars.insert("nothing".to_string(), VariableValue::Empty);
+
+        let json = serde_json::to_string(&vars).expect("should never fail");
+        let read_vars: HashMap<String, VariableValue> =
+            serde_json::from_str(&json).expect("should never fail");
+        assert_eq!(read_vars, vars);
+
+        let tokens = Tokenizer::new()
+            .tokenize_borrowed("if !cond ; hello : world")
+            .expect("should never fail");
+        let json = serde_json::to_string(&tokens).expect("should never fail");
+        let read_tokens: Vec<crate::Token> =
+            serde_json::from_str(&json).expect("should never fail");
+        assert_eq!(read_tokens, tokens);
+
+        let ast = parse(tokens).expect("should never fail");
+        let json = serde_json::to_string(&ast).expect("should never fail");
+        let read_ast: Ast = serde_json::from_str(&json).expect("should never fail");
+        assert_eq!(read_ast, ast);
+    }
+
+    #[test]
+    fn tausch_error_as_dyn_error() {
+        let vars = HashMap::<String, VariableValue>::new();
+        let run =
+            || -> Result<VariableValue, Box<dyn std::error::Error>> { Ok(eval(&vars, "missing")?) };
+
+        assert_eq!(
+            run().expect_err("should always fail").to_string(),
+            "Parsing failed: Variable 'missing' does not exist!"
+        );
+        assert_eq!(
+            TauschError::Tokenizer {
+                message: "Unknown token: '?' at column 1".to_string(),
+                span: None,
+            }
+            .to_string(),
+            "Tokenizing failed: Unknown token: '?' at column 1"
+        );
+    }
+
+    #[test]
+    fn eval_if_negate_comparison() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("yes".to_string(), VariableValue::Str("yes".to_string()));
+        vars.insert("no".to_string(), VariableValue::Str("no".to_string()));
+        vars.insert("s1".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("s2".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("s3".to_string(), VariableValue::Str("69".to_string()));
+        vars.insert("b1".to_string(), VariableValue::Bool(true));
+        vars.insert("b2".to_string(), VariableValue::Bool(false));
+
+        let eval_str = |input: &str| eval(&vars, input).expect("should never fail").to_string();
+
+        assert_eq!(eval_str("if s1 == s2 ; yes : no"), "yes");
+        assert_eq!(eval_str("if !s1 == s2 ; yes : no"), "no");
+        assert_eq!(eval_str("if !s1 == s3 ; yes : no"), "yes");
+        assert_eq!(eval_str("if !s1 != s3 ; yes : no"), "no");
+        assert_eq!(eval_str("if b1 == b2 ; yes : no"), "no");
+        assert_eq!(eval_str("if !b1 == b2 ; yes : no"), "yes");
+        assert_eq!(eval_str("if !b1 == b1 ; yes : no"), "no");
+        assert_eq!(eval_str("if !b2 ; yes : no"), "yes");
+    }
+
+    #[test]
+    fn eval_concat() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert(
+            "greeting".to_string(),
+            VariableValue::Str("Hello, ".to_string()),
+        );
+        vars.insert("name".to_string(), VariableValue::Str("World".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+
+        assert_eq!(
+            eval(&vars, "greeting name").expect("should never fail"),
+            VariableValue::Str("Hello, World".to_string())
+        );
+        assert_eq!(
+            eval(&vars, "name \" is \" cond 1").expect("should never fail"),
+            VariableValue::Str("World is true1".to_string())
+        );
+        assert!(eval(&vars, "greeting missing").is_err());
+    }
+
+    #[test]
+    fn tokenize_free_function() {
+        let tokens = tokenize("if cond ; hello : world").expect("should never fail");
+
+        assert_eq!(
+            tokens,
+            Tokenizer::new()
+                .tokenize("if cond ; hello : world".to_string())
+                .expect("should never fail")
+        );
+        assert_eq!(tokens[2].typ, TokenType::IfEnd);
+        assert!(tokenize("hello ?").is_err());
+    }
+
+    #[test]
+    fn eval_empty_input() {
+        let vars = HashMap::<String, VariableValue>::new();
+
+        assert_eq!(
+            eval(&vars, "").expect("should never fail"),
+            VariableValue::Empty
+        );
+        assert_eq!(
+            eval(&vars, "   ").expect("should never fail"),
+            VariableValue::Empty
+        );
+        assert_eq!(
+            eval(&vars, "# only a comment").expect("should never fail"),
+            VariableValue::Empty
+        );
+
+        let options = ParseOptions {
+            reject_empty: true,
+            ..ParseOptions::default()
+        };
+        assert!(matches!(
+            parse_with(tokenize("  ").expect("should never fail"), &options),
+            Err(TauschError::Parser(ParserError::NoTokens))
+        ));
+    }
+
+    #[test]
+    fn eval_if_literal_branches() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if cond ; \"yes\" : \"no\"").expect("should never fail"),
+            VariableValue::Str("yes".to_string())
+        );
+        assert_eq!(
+            eval(&vars, "if ncond ; hello : 69").expect("should never fail"),
+            VariableValue::Int(69)
+        );
+        assert_eq!(
+            eval(&vars, "if cond ; hello : 1.5").expect("should never fail"),
+            VariableValue::Str("42".to_string())
+        );
+        assert_eq!(
+            eval(&vars, "if ncond ; -2.5 : hello").expect("should never fail"),
+            VariableValue::Str("42".to_string())
+        );
+        assert_eq!(
+            eval(&vars, "if ncond ; \"yes\" : ncond ; 1 : \"no\"").expect("should never fail"),
+            VariableValue::Str("no".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_bool_literals() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert(
+            "true".to_string(),
+            VariableValue::Str("shadowed".to_string()),
+        );
+
+        assert_eq!(
+            eval(&vars, "true").expect("should never fail"),
+            VariableValue::Bool(true)
+        );
+        assert_eq!(
+            eval(&vars, "if true ; hello : false").expect("should never fail"),
+            VariableValue::Str("42".to_string())
+        );
+        assert_eq!(
+            eval(&vars, "if false ; hello : false").expect("should never fail"),
+            VariableValue::Bool(false)
+        );
+        assert_eq!(
+            eval(&vars, "if !false && true ; true").expect("should never fail"),
+            VariableValue::Bool(true)
+        );
+        assert_eq!(
+            token_stream("true falsey"),
+            vec![
+                (TokenType::BoolLiteral, "true".to_string()),
+                (TokenType::Variable, "falsey".to_string()),
+            ]
+        );
+        assert_eq!(
+            eval(&vars, "if true == hello ; hello").expect("should never fail"),
+            VariableValue::Empty
+        );
+    }
+
+    #[test]
+    fn eval_custom_variable_source() {
+        let source = |name: &str| match name {
+            "cond" => Some(VariableValue::Bool(true)),
+            "hello" => Some(VariableValue::Int(42)),
+            _ => None,
+        };
+
+        assert_eq!(
+            parse(tokenize("if cond ; hello : world").expect("should never fail"))
+                .expect("should never fail")
+                .eval(&source)
+                .expect("should never fail"),
+            VariableValue::Int(42)
+        );
+        assert!(matches!(
+            parse(tokenize("if !cond ; hello : world").expect("should never fail"))
+                .expect("should never fail")
+                .eval(&source),
+            Err(TauschError::Parser(ParserError::UndefinedVariable { name, .. })) if name == "world"
+        ));
+
+        let mut vars = BTreeMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        assert_eq!(
+            eval(&vars, "hello").expect("should never fail"),
+            VariableValue::Str("42".to_string())
+        );
+    }
+
+    #[test]
+    fn eval_if_else_generic_source() {
+        let mut vars = BTreeMap::<String, VariableValue>::new();
+        let var_hello = VariableValue::Str("42".to_string());
+        let var_world = VariableValue::Str("69".to_string());
+        vars.insert("hello".to_string(), var_hello.clone());
+        vars.insert("world".to_string(), var_world.clone());
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if cond ; hello").expect("should never fail"),
+            var_hello
+        );
+        assert_eq!(
+            eval(&vars, "if ncond ; hello").expect("should never fail"),
+            VariableValue::Empty
+        );
+        assert_eq!(
+            eval(&vars, "if cond ; hello : world").expect("should never fail"),
+            var_hello
+        );
+        assert_eq!(
+            eval(&vars, "if ncond ; hello : world").expect("should never fail"),
+            var_world
+        );
+    }
+
+    #[test]
+    fn validate_templates() {
+        assert!(validate("missing").is_ok());
+        assert!(validate("if missing ; a : b ; c").is_ok());
+        assert!(validate("if (a || b) && n < 3 ; \"x\" : 1.5").is_ok());
+        assert!(validate("").is_ok());
+
+        assert!(matches!(
+            validate("if a ; b extra"),
+            Err(TauschError::Parser(ParserError::TrailingToken { .. }))
+        ));
+        assert!(matches!(
+            validate("if \"a\" ; b"),
+            Err(TauschError::Parser(ParserError::UnexpectedToken { .. }))
+        ));
+        assert!(matches!(
+            validate("if 1 < \"2\" ; b"),
+            Err(TauschError::Parser(ParserError::UnexpectedToken { .. }))
+        ));
+        assert!(matches!(
+            validate("if a ; \"b"),
+            Err(TauschError::Tokenizer { .. })
+        ));
+    }
+
+    #[test]
+    fn list_referenced_variables() {
+        assert_eq!(
+            referenced_variables("hello").expect("should never fail"),
+            vec!["hello"]
+        );
+        assert_eq!(
+            referenced_variables("if cond ; hello : world").expect("should never fail"),
+            vec!["cond", "hello", "world"]
+        );
+        assert_eq!(
+            referenced_variables(
+                "if !a == b && n < 3 ; if cond ; hello : a : cond ; world : hello"
+            )
+            .expect("should never fail"),
+            vec!["a", "b", "n", "cond", "hello", "world"]
+        );
+        assert!(
+            referenced_variables("\"x\" 1 true")
+                .expect("should never fail")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn eval_if_parentheses() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+        vars.insert("world".to_string(), VariableValue::Str("69".to_string()));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if cond || cond && ncond ; hello : world")
+                .expect("should never fail")
+                .to_string(),
+            "42"
+        );
+        assert_eq!(
+            eval(&vars, "if (cond || cond) && ncond ; hello : world")
+                .expect("should never fail")
+                .to_string(),
+            "69"
+        );
+        assert_eq!(
+            eval(&vars, "if !(ncond || ncond) && ((cond)) ; hello : world")
+                .expect("should never fail")
+                .to_string(),
+            "42"
+        );
+
+        assert!(matches!(
+            eval(&vars, "if (cond || ncond ; hello : world"),
+            Err(TauschError::Parser(ParserError::UnexpectedToken { found, column: 19, .. }))
+                if found == ";"
+        ));
+        assert!(matches!(
+            eval(&vars, "if cond) ; hello"),
+            Err(TauschError::Parser(ParserError::UnexpectedToken { found, .. })) if found == ")"
+        ));
+        assert!(matches!(
+            eval(&vars, &format!("if {}cond ; hello", "(".repeat(100_000))),
+            Err(TauschError::Parser(ParserError::NestingTooDeep { .. }))
+        ));
+    }
+
+    #[test]
+    fn parse_nesting_limit() {
+        let nested = |depth: usize| format!("{}hello", "if cond ; ".repeat(depth));
+        let options = ParseOptions::default();
+
+        assert!(
+            parse_with(
+                tokenize(&nested(options.max_depth)).expect("should never fail"),
+                &options
+            )
+            .is_ok()
+        );
+        assert!(matches!(
+            parse_with(
+                tokenize(&nested(options.max_depth + 1)).expect("should never fail"),
+                &options
+            ),
+            Err(TauschError::Parser(ParserError::NestingTooDeep {
+                max_depth: 128,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            parse(tokenize(&nested(100_000)).expect("should never fail")),
+            Err(TauschError::Parser(ParserError::NestingTooDeep { .. }))
+        ));
+
+        let chain = format!("if a ; x{} : z", " : b ; y".repeat(3));
+        let options = ParseOptions {
+            max_depth: 3,
+            ..ParseOptions::default()
+        };
+        assert!(parse_with(tokenize(&chain).expect("should never fail"), &options).is_err());
+    }
+
+    #[test]
+    fn eval_float_literal() {
+        let vars = HashMap::<String, VariableValue>::new();
+
+        assert_eq!(
+            eval(&vars, "1.5").expect("should never fail"),
+            VariableValue::Float(1.5)
+        );
+        assert_eq!(
+            eval(&vars, "-0.25").expect("should never fail"),
+            VariableValue::Float(-0.25)
+        );
+        assert!(tokenize("1.5.5").is_err());
+    }
+
+    #[test]
+    fn eval_if_ordering() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("yes".to_string(), VariableValue::Str("yes".to_string()));
+        vars.insert("no".to_string(), VariableValue::Str("no".to_string()));
+        vars.insert("one".to_string(), VariableValue::Int(1));
+        vars.insert("two".to_string(), VariableValue::Int(2));
+        vars.insert("half".to_string(), VariableValue::Float(0.5));
+
+        let eval_str = |input: &str| eval(&vars, input).expect("should never fail").to_string();
+
+        assert_eq!(eval_str("if one < two ; yes : no"), "yes");
+        assert_eq!(eval_str("if two < one ; yes : no"), "no");
+        assert_eq!(eval_str("if one <= 1 ; yes : no"), "yes");
+        assert_eq!(eval_str("if two<=1 ; yes : no"), "no");
+        assert_eq!(eval_str("if half > one ; yes : no"), "no");
+        assert_eq!(eval_str("if one > half ; yes : no"), "yes");
+        assert_eq!(eval_str("if 0.5 >= half ; yes : no"), "yes");
+        assert_eq!(eval_str("if 0.25 >= half ; yes : no"), "no");
+        assert_eq!(eval_str("if !1 < 2 ; yes : no"), "no");
+    }
+
+    #[test]
+    fn eval_if_ordering_nan() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("yes".to_string(), VariableValue::Str("yes".to_string()));
+        vars.insert("no".to_string(), VariableValue::Str("no".to_string()));
+        vars.insert("nan".to_string(), VariableValue::Float(f64::NAN));
+
+        for op in ["<", "<=", ">", ">="] {
+            assert_eq!(
+                eval(&vars, &format!("if nan {op} 1 ; yes : no"))
+                    .expect("should never fail")
+                    .to_string(),
+                "no"
+            );
+            assert_eq!(
+                eval(&vars, &format!("if nan {op} nan ; yes : no"))
+                    .expect("should never fail")
+                    .to_string(),
+                "no"
+            );
+        }
+    }
+
+    #[test]
+    fn eval_if_ordering_type_error() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Str("42".to_string()));
+
+        assert!(matches!(
+            eval(&vars, "if hello < 69 ; hello : hello"),
+            Err(TauschError::Parser(ParserError::TypeMismatch {
+                expected: "number",
+                found: "str",
+                ..
+            }))
+        ));
+        assert!(eval(&vars, "if 1 < \"2\" ; hello : hello").is_err());
+    }
+
+    #[test]
+    fn eval_arith_int() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+
+        assert!(matches!(
+            eval(&vars, "hello + 1 * 2"),
+            Ok(VariableValue::Int(44))
+        ));
+        assert!(matches!(
+            eval(&vars, "hello -1"),
+            Ok(VariableValue::Int(41))
+        ));
+        assert!(matches!(
+            eval(&vars, "10 - 2 - 3"),
+            Ok(VariableValue::Int(5))
+        ));
+        assert!(matches!(eval(&vars, "-1 + 2"), Ok(VariableValue::Int(1))));
+        assert!(matches!(
+            eval(&vars, "9223372036854775807 + 1"),
+            Err(TauschError::Parser(ParserError::IntegerOverflow))
+        ));
+    }
+
+    #[test]
+    fn eval_arith_float() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+        vars.insert("yes".to_string(), VariableValue::Str("yes".to_string()));
+        vars.insert("no".to_string(), VariableValue::Str("no".to_string()));
+
+        assert!(matches!(
+            eval(&vars, "hello / 4.0"),
+            Ok(VariableValue::Float(10.5))
+        ));
+        assert!(matches!(
+            eval(&vars, "hello / 4"),
+            Ok(VariableValue::Int(10))
+        ));
+        assert_eq!(
+            eval(&vars, "if hello / 4.0 > 10 ; yes : no")
+                .expect("should never fail")
+                .to_string(),
+            "yes"
+        );
+        assert!(matches!(
+            eval(&vars, "yes + 1"),
+            Err(TauschError::Parser(ParserError::TypeMismatch {
+                expected: "number",
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn eval_arith_divide_by_zero() {
+        let vars = HashMap::<String, VariableValue>::new();
+
+        assert!(matches!(
+            eval(&vars, "1 / 0"),
+            Err(TauschError::Parser(ParserError::DivisionByZero))
+        ));
+        assert!(matches!(
+            eval(&vars, "1.5 / 0"),
+            Err(TauschError::Parser(ParserError::DivisionByZero))
+        ));
+    }
+
+    #[test]
+    fn token_type_eq() {
+        assert_ne!(TokenType::Variable, TokenType::IfEnd);
+        assert_ne!(
+            Token::new(TokenType::Variable, ";"),
+            Token::new(TokenType::IfEnd, ";")
+        );
+        assert_eq!(
+            Token::new(TokenType::Variable, "hello"),
+            Token::new(TokenType::Variable, "hello")
+        );
+    }
+
+    #[test]
+    fn expect_token_mismatch() {
+        let tokens = tokenize("hello").expect("should never fail");
+        assert!(matches!(
+            expect_token(&mut tokens.iter(), TokenType::IfEnd, String::new()),
+            Err(TauschError::Parser(ParserError::UnexpectedToken { .. }))
+        ));
+        assert!(expect_token(&mut tokens.iter(), TokenType::Variable, String::new()).is_ok());
+    }
+
+    #[test]
+    fn render_error_caret() {
+        let vars = HashMap::<String, VariableValue>::new();
+        let input = "if cond ; hello : world :";
+        let err = eval(&vars, input).expect_err("should never succeed");
+        assert_eq!(
+            render_error(input, &err),
+            format!("if cond ; hello : world :\n{}^\n{err}", " ".repeat(24))
+        );
+
+        let input = "hello\nworld ;";
+        let err = eval(&vars, input).expect_err("should never succeed");
+        let rendered = render_error(input, &err);
+        let mut lines = rendered.lines();
+        assert_eq!(lines.next(), Some("world ;"));
+        assert_eq!(lines.next(), Some("      ^"));
+
+        // Spans that end up inside of a character of `input` are not underlined.
+        for (start, end) in [(1, 2), (0, 1), (5, 6)] {
+            let err = TauschError::Tokenizer {
+                message: "oops".to_string(),
+                span: Some(Span { start, end }),
+            };
+            assert_eq!(render_error("é", &err), err.to_string());
+        }
+        let input = "\"é\"\na ?";
+        let err = eval_all(&vars, input).expect_err("should never succeed");
+        assert_eq!(render_error(input, &err), err.to_string());
+    }
+
+    #[test]
+    fn eval_recursive_indirection() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+        vars.insert("greeting".to_string(), "=if cond; hello : 0".into());
+        vars.insert("plain".to_string(), "if cond".into());
+
+        assert!(matches!(
+            eval_recursive(&vars, "greeting", "="),
+            Ok(VariableValue::Int(42))
+        ));
+        assert_eq!(
+            eval_recursive(&vars, "plain", "=")
+                .expect("should never fail")
+                .to_string(),
+            "if cond"
+        );
+    }
+
+    #[test]
+    fn eval_recursive_cycle() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("a".to_string(), "=b".into());
+        vars.insert("b".to_string(), "=a".into());
+        vars.insert("me".to_string(), "=me".into());
+
+        assert!(matches!(
+            eval_recursive(&vars, "me", "="),
+            Err(TauschError::Parser(ParserError::RecursiveVariable { chain })) if chain == ["me", "me"]
+        ));
+        assert!(matches!(
+            eval_recursive(&vars, "a", "="),
+            Err(TauschError::Parser(ParserError::RecursiveVariable { chain })) if chain == ["a", "b", "a"]
+        ));
+    }
+
+    #[test]
+    fn eval_recursive_lazy_branches() {
+        let vars = vars! {
+            "x" => "@missing",
+            "zero" => "@1 / 0",
+            "me" => "@me",
+            "n" => "@2",
+            "twice" => "@n + n",
+        };
+
+        for input in [
+            "if false ; x : \"ok\"",
+            "if true ; \"ok\" : zero",
+            "if defined(n) ; \"ok\" : me",
+        ] {
+            assert_eq!(
+                eval_recursive(&vars, input, "@"),
+                Ok(VariableValue::Str("ok".to_string())),
+                "{input}"
+            );
+        }
+        assert_eq!(
+            eval_recursive(&vars, "twice * n", "@"),
+            Ok(VariableValue::Int(8))
+        );
+        assert!(matches!(
+            eval_recursive(&vars, "if true ; x : \"ok\"", "@"),
+            Err(TauschError::Parser(ParserError::UndefinedVariable { name, .. })) if name == "missing"
+        ));
+        assert_eq!(
+            eval_recursive(&vars, "zero ?? 1", "@"),
+            Err(TauschError::Parser(ParserError::DivisionByZero))
+        );
+        // `defined` can't hide a failing template.
+        assert!(matches!(
+            eval_recursive(&vars, "if defined(me) ; 1 : 2", "@"),
+            Err(TauschError::Parser(ParserError::RecursiveVariable { .. }))
+        ));
+    }
+
+    #[test]
+    fn tokenize_with_stats() {
+        let (tokens, stats) = Tokenizer::new()
+            .tokenize_with_stats("if cond ; hello : world")
+            .expect("should never fail");
+
+        assert_eq!(stats.total, tokens.len());
+        assert_eq!(stats.total, 6);
+        assert_eq!(stats.count(TokenType::Variable), 3);
+        assert_eq!(stats.count(TokenType::IfStart), 1);
+        assert_eq!(stats.count(TokenType::IfEnd), 1);
+        assert_eq!(stats.count(TokenType::IfElse), 1);
+        assert_eq!(stats.count(TokenType::StrLiteral), 0);
+    }
+
+    #[test]
+    fn eval_if_eq_literal() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("x".to_string(), "yes".into());
+        vars.insert("n".to_string(), VariableValue::Int(5));
+        vars.insert("flag".to_string(), VariableValue::Bool(true));
+
+        for (input, expected) in [
+            ("if x == \"yes\" ; 1 : 0", 1),
+            ("if \"no\" == x ; 1 : 0", 0),
+            ("if x != \"no\" ; 1 : 0", 1),
+            ("if n == 5 ; 1 : 0", 1),
+            ("if 5.0 == n ; 1 : 0", 1),
+            ("if n != 5.5 ; 1 : 0", 1),
+            ("if n == 2 + 3 ; 1 : 0", 1),
+            ("if flag == true ; 1 : 0", 1),
+            ("if false == flag ; 1 : 0", 0),
+        ] {
+            assert!(
+                matches!(eval(&vars, input), Ok(VariableValue::Int(val)) if val == expected),
+                "{input}"
+            );
+        }
+        assert!(validate("if \"yes\" ; 1 : 0").is_err());
+        assert!(validate("if x == ; 1 : 0").is_err());
+    }
+
+    #[test]
+    fn tokenize_interned_dedup() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("a".to_string(), VariableValue::Int(1));
+        vars.insert("b".to_string(), VariableValue::Int(2));
+
+        let input = "a b ".repeat(1000);
+        let mut interner = StringInterner::new();
+        let tokens = Tokenizer::new()
+            .tokenize_interned(&input, &mut interner)
+            .expect("should never fail");
+
+        assert_eq!(tokens.len(), 2000);
+        assert_eq!(interner.len(), 2);
+        assert_eq!(interner.resolve(tokens[0].label), "a");
+        assert_eq!(tokens[0].label, tokens[1998].label);
+        // The map and the list of strings don't keep separate copies.
+        for (string, symbol) in &interner.ids {
+            assert!(std::sync::Arc::ptr_eq(
+                string,
+                &interner.strings[symbol.0 as usize]
+            ));
+        }
+        assert_eq!(
+            eval_interned(&vars, &tokens, &interner)
+                .expect("should never fail")
+                .to_string(),
+            "12".repeat(1000)
+        );
+    }
+
+    #[test]
+    fn eval_untaken_default() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        let options = EvalOptions {
+            untaken_default: Some(VariableValue::Str(String::new())),
+            ..EvalOptions::default()
+        };
+        let ast = parse(tokenize("if ncond ; hello").expect("should never fail"))
+            .expect("should never fail");
+
+        assert_eq!(
+            ast.eval_with(&vars, &options).expect("should never fail"),
+            VariableValue::Str(String::new())
+        );
+        assert_eq!(
+            ast.eval(&vars).expect("should never fail"),
+            VariableValue::Empty
+        );
+    }
+
+    #[test]
+    fn tokenizer_reserved_tokens() {
+        let tokenizer = Tokenizer::new();
+        let labels: Vec<&str> = tokenizer
+            .reserved_tokens()
+            .iter()
+            .map(|tok| tok.label.as_ref())
+            .collect();
+
+        for label in ["if", ";", ":", "!"] {
+            assert!(labels.contains(&label), "{label}");
+        }
+        assert!(
+            tokenizer
+                .reserved_tokens()
+                .contains(&reserved_token(TokenType::IfStart, "if"))
+        );
+    }
+
+    #[test]
+    fn error_eq() {
+        let err = tokenize("\"unterminated").expect_err("should never succeed");
+        assert_eq!(
+            err,
+            tokenize("\"unterminated").expect_err("should never succeed")
+        );
+        assert_eq!(err.clone(), err);
+        assert_ne!(err, tokenize("a\\").expect_err("should never succeed"));
+        assert_eq!(
+            validate("if cond"),
+            Err(TauschError::Parser(ParserError::UnexpectedEof {
+                expected: "Expected ';' after the condition of 'if'!".to_string(),
+                expected_tokens: vec![TokenType::IfEnd],
+            }))
+        );
+    }
+
+    #[test]
+    fn eval_if_compare_group() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+        vars.insert("world".to_string(), VariableValue::Int(69));
+
+        for (input, expected) in [
+            ("if (if cond ; hello : world) == hello ; 1 : 0", 1),
+            ("if (if ncond ; hello : world) == hello ; 1 : 0", 0),
+            ("if world != (if ncond ; hello : world) ; 1 : 0", 0),
+            ("if (if cond ; hello : world) < 50 ; 1 : 0", 1),
+            ("if ((hello)) == 42 && cond ; 1 : 0", 1),
+            ("if (cond || ncond) && cond ; 1 : 0", 1),
+        ] {
+            assert!(
+                matches!(eval(&vars, input), Ok(VariableValue::Int(val)) if val == expected),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn ast_rename_variable() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("on".to_string(), VariableValue::Bool(false));
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+
+        let mut ast =
+            parse(tokenize("if cond && cond != hello ; cond : hello").expect("should never fail"))
+                .expect("should never fail");
+        assert!(ast.eval(&vars).is_err());
+
+        assert_eq!(ast.rename_variable("cond", "on"), 3);
+        assert_eq!(ast.rename_variable("missing", "on"), 0);
+        assert_eq!(ast.referenced_variables(), ["on", "hello"]);
+        assert_eq!(
+            ast.eval(&vars).expect("should never fail"),
+            VariableValue::Int(42)
+        );
+    }
+
+    #[test]
+    fn eval_all_crlf() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+        vars.insert("world".to_string(), VariableValue::Int(69));
+
+        let results = eval_all(&vars, "hello\r\nworld\r\n").expect("should never fail");
+        assert_eq!(results, [VariableValue::Int(42), VariableValue::Int(69)]);
+
+        let results = eval_all(&vars, "hello\r\n\r\nworld\nhello\r").expect("should never fail");
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            eval(&vars, "hello\r\n").expect("should never fail"),
+            VariableValue::Int(42)
+        );
+    }
+
+    #[test]
+    fn tokenize_max_identifier_len() {
+        let tokenizer = Tokenizer::new().with_max_identifier_len(8);
+        assert!(tokenizer.tokenize_borrowed("a2345678").is_ok());
+        assert!(matches!(
+            tokenizer.tokenize_borrowed("if a23456789"),
+            Err(TauschError::Tokenizer { message, .. }) if message == "Identifier longer than 8 characters at column 4"
+        ));
+        assert!(tokenizer.tokenize_borrowed("a\\:345678").is_ok());
+
+        let long = "a".repeat(1025);
+        assert!(tokenize(&long[1..]).is_ok());
+        assert!(tokenize(&long).is_err());
+    }
+
+    #[test]
+    fn eval_with_options() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+        vars.insert("yes".to_string(), "True".into());
+
+        let options = EvalOptions {
+            coerce_bool_strings: true,
+            case_insensitive_keywords: true,
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            eval_with(&options, &vars, "IF yes ; hello").expect("should never fail"),
+            VariableValue::Int(42)
+        );
+        assert!(eval(&vars, "IF yes ; hello").is_err());
+
+        let options = EvalOptions {
+            truthiness: true,
+            parse: ParseOptions {
+                max_depth: 1,
+                ..ParseOptions::default()
+            },
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            eval_with(&options, &vars, "if hello ; hello").expect("should never fail"),
+            VariableValue::Int(42)
+        );
+        assert!(eval_with(&options, &vars, "if hello ; if hello ; hello").is_err());
+    }
+
+    #[test]
+    fn eval_traced_branches() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+        vars.insert("world".to_string(), VariableValue::Int(69));
+
+        assert_eq!(
+            eval_traced(&vars, "if cond ; hello : world").expect("should never fail"),
+            (VariableValue::Int(42), BranchTaken::True)
+        );
+        assert_eq!(
+            eval_traced(&vars, "if ncond ; hello : world").expect("should never fail"),
+            (VariableValue::Int(69), BranchTaken::Else)
+        );
+        assert_eq!(
+            eval_traced(&vars, "if ncond ; hello : ncond ; world").expect("should never fail"),
+            (VariableValue::Empty, BranchTaken::Else)
+        );
+        assert_eq!(
+            eval_traced(&vars, "(if ncond ; hello)").expect("should never fail"),
+            (VariableValue::Empty, BranchTaken::None)
+        );
+        assert_eq!(
+            eval_traced(&vars, "hello").expect("should never fail"),
+            (VariableValue::Int(42), BranchTaken::NotAnIf)
+        );
+    }
+
+    #[test]
+    fn eval_if_str_literal_whitespace() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+
+        assert_eq!(
+            eval(&vars, "if cond ; \"multi word\" : \"other\"").expect("should never fail"),
+            VariableValue::Str("multi word".to_string())
+        );
+        assert_eq!(
+            eval(&vars, "if ncond ; \"a\" : \"  two\tspaces ; : \"").expect("should never fail"),
+            VariableValue::Str("  two\tspaces ; : ".to_string())
+        );
+    }
+
+    #[test]
+    fn token_into_owned() {
+        let input = String::from("if cond ; hello");
+        let tokens: Vec<Token<'static>> = tokenize(&input)
+            .expect("should never fail")
+            .into_iter()
+            .map(Token::into_owned)
+            .collect();
+        drop(input);
+
+        assert_eq!(tokens[1].label, "cond");
+        assert!(matches!(tokens[1].label, Cow::Owned(_)));
+        assert_eq!(tokens[3].span, Span { start: 10, end: 15 });
+    }
+
+    #[test]
+    fn variable_value_hash() {
+        let values = [
+            VariableValue::Empty,
+            VariableValue::Bool(true),
+            VariableValue::Int(1),
+            VariableValue::Float(1.0),
+            VariableValue::Float(0.0),
+            VariableValue::Float(-0.0),
+            VariableValue::Float(f64::NAN),
+            VariableValue::Str("1".to_string()),
+        ];
+        let set: HashSet<VariableValue> = values.iter().cloned().collect();
+        assert_eq!(set.len(), values.len());
+
+        for val in values {
+            assert!(set.contains(&val));
+        }
+        assert!(!set.contains(&VariableValue::Int(2)));
+    }
+
+    #[test]
+    fn eval_transforms() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("name".to_string(), " Tausch ".into());
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+        vars.insert("cond".to_string(), VariableValue::Bool(true));
+
+        assert_eq!(
+            eval(&vars, "name | upper").expect("should never fail"),
+            VariableValue::Str(" TAUSCH ".to_string())
+        );
+        assert_eq!(
+            eval(&vars, "name | trim | lower").expect("should never fail"),
+            VariableValue::Str("tausch".to_string())
+        );
+        assert_eq!(
+            eval(&vars, "name | len").expect("should never fail"),
+            VariableValue::Int(8)
+        );
+        assert_eq!(
+            eval(&vars, "if cond ; hello * 100 | len").expect("should never fail"),
+            VariableValue::Int(4)
+        );
+        assert_eq!(
+            eval(&vars, "(name hello) | trim").expect("should never fail"),
+            VariableValue::Str("Tausch 42".to_string())
+        );
+        assert!(matches!(
+            validate("name | reverse"),
+            Err(TauschError::Parser(ParserError::UnknownTransform { name, column: 8, .. })) if name == "reverse"
+        ));
+        assert!(validate("name |").is_err());
+    }
+
+    #[test]
+    fn eval_custom_function() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+        vars.insert("world".to_string(), VariableValue::Int(69));
+
+        let options = EvalOptions {
+            functions: FunctionRegistry::new().with_function("concat", |args| {
+                Ok(VariableValue::Str(
+                    args.iter()
+                        .map(VariableValue::to_string)
+                        .collect::<Vec<_>>()
+                        .join("-"),
+                ))
+            }),
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            eval_with(&options, &vars, "concat(hello \"and\" world + 1)")
+                .expect("should never fail"),
+            VariableValue::Str("42-and-70".to_string())
+        );
+        assert_eq!(
+            eval_with(
+                &options,
+                &vars,
+                "concat(concat(hello world) (if true ; 1)) | len"
+            )
+            .expect("should never fail"),
+            VariableValue::Int(7)
+        );
+        assert_eq!(
+            eval_with(&options, &vars, "concat()").expect("should never fail"),
+            VariableValue::Str(String::new())
+        );
+        assert!(matches!(
+            eval(&vars, "concat(hello)"),
+            Err(TauschError::Parser(ParserError::UnknownFunction { name })) if name == "concat"
+        ));
+        assert!(validate("concat(hello").is_err());
+        assert_eq!(
+            referenced_variables("concat(hello world)").expect("should never fail"),
+            ["hello", "world"]
+        );
+    }
+
+    #[test]
+    fn eval_to_string_results() {
+        let mut vars = HashMap::<String, VariableValue>::new();
         vars.insert("cond".to_string(), VariableValue::Bool(true));
-        vars.insert("ncond".to_string(), VariableValue::Bool(false));
+        vars.insert("hello".to_string(), "42".into());
 
         assert_eq!(
-            eval(vars.clone(), "if ncond ; hello : world".to_string()).expect("should never fail"),
-            var_world
+            eval_to_string(&vars, "cond").expect("should never fail"),
+            "true"
+        );
+        assert_eq!(
+            eval_to_string(&vars, "hello").expect("should never fail"),
+            "42"
+        );
+        assert_eq!(
+            eval_to_string(&vars, "if !cond ; hello").expect("should never fail"),
+            ""
+        );
+        assert!(eval_to_string(&vars, "missing").is_err());
+    }
+
+    #[test]
+    fn parse_chained_comparison() {
+        for input in [
+            "if a == b == c ; a",
+            "if a != b == c ; a",
+            "if 1 < a < 3 ; a",
+            "if !a == b != c ; a",
+        ] {
+            assert!(
+                matches!(
+                    validate(input),
+                    Err(TauschError::Parser(ParserError::UnexpectedToken { expected, .. }))
+                        if expected.contains("chained")
+                ),
+                "{input}"
+            );
+        }
+        assert!(validate("if a == b && b == c ; a").is_ok());
+    }
+
+    #[test]
+    fn eval_ref_borrows() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("cond".to_string(), VariableValue::Bool(false));
+        vars.insert("hello".to_string(), "a long string".into());
+
+        for input in ["hello", "if cond ; 1 : hello", "(if cond ; 1) ?? hello"] {
+            let val = eval_ref(&vars, input).expect("should never fail");
+            assert!(
+                matches!(val, ValueRef::Borrowed(val) if std::ptr::eq(val, &vars["hello"])),
+                "{input}"
+            );
+        }
+        assert_eq!(
+            eval_ref(&vars, "hello \"!\"").expect("should never fail"),
+            ValueRef::Owned(VariableValue::Str("a long string!".to_string()))
+        );
+        assert!(eval_ref(&vars, "missing").is_err());
+    }
+
+    #[test]
+    fn tokenize_lenient() {
+        let tokenizer = Tokenizer::new().with_lenient(true);
+        let (tokens, diagnostics) = tokenizer
+            .tokenize_with_diagnostics("if cond ? ; hello")
+            .expect("should never fail");
+
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|tok| tok.label.as_ref())
+                .collect::<Vec<_>>(),
+            ["if", "cond", ";", "hello"]
+        );
+        assert_eq!(
+            diagnostics,
+            [Diagnostic {
+                message: "Unknown token: '?' at column 9".to_string(),
+                span: Some(Span { start: 8, end: 9 }),
+            }]
+        );
+        assert!(
+            Tokenizer::new()
+                .tokenize_borrowed("if cond ? ; hello")
+                .is_err()
+        );
+        assert!(Tokenizer::new().tokenize_with_diagnostics("a $ b").is_err());
+    }
+
+    #[test]
+    fn ast_fold_constants() {
+        let fold = |input: &str| {
+            parse(tokenize(input).expect("should never fail"))
+                .expect("should never fail")
+                .fold_constants()
+        };
+
+        assert_eq!(fold("if true ; a : b"), Ast::Var("a".to_string()));
+        assert_eq!(fold("if !true || 1 > 2 ; a : b"), Ast::Var("b".to_string()));
+        assert_eq!(fold("1 + 2 * 3"), Ast::Literal(VariableValue::Int(7)));
+        assert_eq!(
+            fold("if \"a\" == \"a\" ; (\"x\" | upper \"y\")"),
+            Ast::Literal(VariableValue::Str("Xy".to_string()))
+        );
+        assert_eq!(
+            fold("if true && cond ; a"),
+            Ast::If {
+                cond: Condition::Var("cond".to_string()),
+                on_true: Box::new(Ast::Var("a".to_string())),
+                on_else: None,
+            }
+        );
+        assert!(matches!(fold("1 / 0"), Ast::Arith(..)));
+        assert!(matches!(fold("if false ; a"), Ast::If { .. }));
+        assert!(matches!(
+            fold("if cond && false ; a"),
+            Ast::If {
+                cond: Condition::And(..),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn eval_if_defined() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+        vars.insert("empty".to_string(), VariableValue::Empty);
+
+        for (input, expected) in [
+            ("if defined(hello) ; 1 : 0", 1),
+            ("if defined(empty) ; 1 : 0", 1),
+            ("if defined(missing) ; 1 : 0", 0),
+            ("if !defined(missing) ; 1 : 0", 1),
+            ("if defined(missing) && missing ; 1 : 0", 0),
+        ] {
+            assert!(
+                matches!(eval(&vars, input), Ok(VariableValue::Int(val)) if val == expected),
+                "{input}"
+            );
+        }
+        assert!(validate("if defined(1) ; 1").is_err());
+        assert!(validate("if defined(hello ; 1").is_err());
+    }
+
+    #[test]
+    fn eval_index() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("name".to_string(), "täusch".into());
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+        vars.insert("one".to_string(), VariableValue::Int(1));
+
+        for (input, expected) in [
+            ("name[0]", "t"),
+            ("name[one]", "ä"),
+            ("name[-1]", "h"),
+            ("name[-6]", "t"),
+            ("name[2 * 2] | upper", "C"),
+            ("\"abc\"[one + 1]", "c"),
+        ] {
+            assert_eq!(
+                eval(&vars, input).expect("should never fail"),
+                VariableValue::Str(expected.to_string()),
+                "{input}"
+            );
+        }
+        for index in ["6", "-7"] {
+            assert!(matches!(
+                eval(&vars, &format!("name[{index}]")),
+                Err(TauschError::Parser(ParserError::IndexOutOfRange {
+                    len: 6,
+                    ..
+                }))
+            ));
+        }
+        assert!(matches!(
+            eval(&vars, "hello[0]"),
+            Err(TauschError::Parser(ParserError::TypeMismatch {
+                expected: "str",
+                found: "int",
+                ..
+            }))
+        ));
+        assert!(validate("name[0").is_err());
+    }
+
+    #[test]
+    fn eval_contains() {
+        let mut vars = HashMap::<String, VariableValue>::new();
+        vars.insert("haystack".to_string(), "foobar".into());
+        vars.insert("needle".to_string(), "bar".into());
+        vars.insert("hello".to_string(), VariableValue::Int(42));
+
+        for (input, expected) in [
+            ("if \"foo\" in haystack; 1 : 0", 1),
+            ("if needle in haystack; 1 : 0", 1),
+            ("if \"Foo\" in haystack; 1 : 0", 0),
+            ("if \"baz\" in haystack; 1 : 0", 0),
+            ("if !\"baz\" in haystack; 1 : 0", 1),
+            ("if haystack in \"xfoobarx\"; 1 : 0", 1),
+            ("if (if true; \"oo\") in haystack; 1 : 0", 1),
+        ] {
+            assert_eq!(
+                eval(&vars, input).expect("should never fail"),
+                VariableValue::Int(expected),
+                "{input}"
+            );
+        }
+        for input in ["if \"4\" in hello; 1", "if hello in haystack; 1"] {
+            assert!(matches!(
+                eval(&vars, input),
+                Err(TauschError::Parser(ParserError::TypeMismatch {
+                    expected: "str",
+                    found: "int",
+                    ..
+                }))
+            ));
+        }
+        assert!(validate("if \"a\" in haystack in haystack; 1").is_err());
+    }
+
+    #[test]
+    fn eval_bytes_input() {
+        let vars = vars! { "café" => 42 };
+
+        assert_eq!(
+            eval_bytes(&vars, "if true; café".as_bytes()).expect("should never fail"),
+            VariableValue::Int(42)
+        );
+        assert_eq!(
+            eval_bytes(&vars, b"\"a\xff\""),
+            Err(TauschError::Tokenizer {
+                message: "Invalid UTF-8 at byte 2".to_string(),
+                span: Some(Span { start: 2, end: 3 }),
+            })
+        );
+        // A truncated multi-byte sequence.
+        assert_eq!(
+            eval_bytes(&vars, &"café".as_bytes()[..4]),
+            Err(TauschError::Tokenizer {
+                message: "Invalid UTF-8 at byte 3".to_string(),
+                span: Some(Span { start: 3, end: 4 }),
+            })
+        );
+    }
+
+    #[test]
+    fn ast_to_source() {
+        let source = |input: &str| {
+            parse(tokenize(input).expect("should never fail"))
+                .expect("should never fail")
+                .to_source()
+        };
+
+        assert_eq!(
+            source("if   cond&&!(a||b);hello:(x??\"y\")"),
+            "if cond && !(a || b) ; hello : (x ?? \"y\")"
+        );
+        assert_eq!(
+            source("if a!=1.0;f( b|upper  \"\\\"\" )"),
+            "if a != 1.0 ; f(b | upper \"\\\"\")"
+        );
+
+        for input in [
+            "hello",
+            "",
+            "a \"b\" -1 2.5 true",
+            "a\\:b \\if",
+            "if cond; hello : world",
+            "if ncond; hello : cond; world : hello",
+            "if cond; (if ncond; hello) : world",
+            "if cond; if ncond; hello : world : hello",
+            "if c; a ?? b",
+            "a ?? (b ?? c) ?? d",
+            "if (a || b) && !c || d && (e || f); x",
+            "if !(a && b) && !c == d && !(c != d); x",
+            "if !(!a); x",
+            "if a + 1 * b >= 2 - c / 3; x",
+            "if (if c; a : b) == \"a\"; x",
+            "if (a b) in hay && \"x\" in (a ?? b) && defined(q); x",
+            "(a b) | upper | len",
+            "name[0][-1] + 1",
+            "f(1 (b ?? c) (if c; d)) | trim",
+            "x ?? if c; a : b",
+            "(a + b) * c - (d - e) + f * g / (h * i)",
+            "(if c; 1 : 2) + (x | len) * 2",
+            "(x ?? y)[0] + z[(n | len)]",
+            "if (n + 1) * 2 > (m - 1) ; x",
+        ] {
+            let ast =
+                parse(tokenize(input).expect("should never fail")).expect("should never fail");
+            let reparsed = parse(tokenize(&ast.to_source()).expect("should never fail"));
+            assert_eq!(reparsed, Ok(ast), "{input}");
+        }
+    }
+
+    #[test]
+    fn tokenize_shares_reserved_labels() {
+        let input = "if cond ; hello : false".to_string();
+        let toker = Tokenizer::new();
+        let tokens = toker.tokenize(input.clone()).expect("should never fail");
+
+        assert_eq!(
+            tokens,
+            toker.tokenize_borrowed(&input).expect("should never fail")
+        );
+        for tok in tokens.iter() {
+            match tok.typ {
+                TokenType::Variable => assert!(matches!(tok.label, Cow::Owned(_))),
+                _ => assert!(matches!(tok.label, Cow::Borrowed(_)), "{tok}"),
+            }
+        }
+
+        // 'IF' does not match the label of the reserved token and gets a label of its own.
+        let tokens = Tokenizer::new()
+            .with_case_insensitive_keywords(true)
+            .tokenize("IF cond ; hello".to_string())
+            .expect("should never fail");
+        assert_eq!(tokens[0].typ, TokenType::IfStart);
+        assert_eq!(tokens[0].label, "IF");
+    }
+
+    #[test]
+    fn eval_if_end_synonym() {
+        let vars = vars! { "cond" => true, "hello" => 42, "world" => 69, "then" => 1 };
+        let toker = Tokenizer::new()
+            .with_if_end_synonym("then")
+            .with_if_end_synonym("=>");
+
+        for input in [
+            "if cond then hello : world",
+            "if cond => hello : world",
+            "if cond ; hello : world",
+            "if !cond then world : hello",
+        ] {
+            assert_eq!(
+                eval_with_tokenizer(&toker, &vars, input).expect("should never fail"),
+                VariableValue::Int(42),
+                "{input}"
+            );
+        }
+        // Without a synonym the ';' stays mandatory and 'then' is a variable.
+        assert!(eval(&vars, "if cond then hello").is_err());
+        assert!(eval(&vars, "if cond hello").is_err());
+        assert!(eval_with_tokenizer(&toker, &vars, "if cond hello").is_err());
+        assert_eq!(
+            eval(&vars, "then").expect("should never fail"),
+            VariableValue::Int(1)
+        );
+    }
+
+    #[test]
+    fn eval_lines_lazily() {
+        let vars = vars! { "hello" => 42, "cond" => true };
+        let results: Vec<_> = eval_lines(&vars, "hello\n\nmissing\nif cond ; \"yes\"").collect();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], Ok(VariableValue::Int(42)));
+        assert!(matches!(
+            &results[1],
+            Err(TauschError::Parser(ParserError::InStatement {
+                index: 1,
+                ..
+            }))
+        ));
+        assert_eq!(results[2], Ok(VariableValue::Str("yes".to_string())));
+
+        // Lines after the first one are not evaluated until they are requested.
+        let mut lines = eval_lines(&vars, "hello\nif");
+        assert_eq!(lines.next(), Some(Ok(VariableValue::Int(42))));
+    }
+
+    #[test]
+    fn variable_value_accessors() {
+        let values = [
+            VariableValue::Bool(true),
+            VariableValue::Str("hi".to_string()),
+            VariableValue::Str(String::new()),
+            VariableValue::Int(0),
+            VariableValue::Float(1.5),
+            VariableValue::Empty,
+        ];
+        let summary: Vec<_> = values
+            .iter()
+            .map(|val| {
+                (
+                    val.is_empty(),
+                    val.is_bool(),
+                    val.is_str(),
+                    val.as_str(),
+                    val.as_bool(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            [
+                (false, true, false, None, Some(true)),
+                (false, false, true, Some("hi"), None),
+                (false, false, true, Some(""), None),
+                (false, false, false, None, None),
+                (false, false, false, None, None),
+                (true, false, false, None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_text() {
+        let vars = vars! { "hello" => 42, "world" => 69, "cond" => true };
+
+        for (text, expected) in [
+            ("no expressions here", "no expressions here"),
+            ("", ""),
+            ("value: {{ hello }}!", "value: 42!"),
+            ("{{hello}} & {{ if cond ; world }}", "42 & 69"),
+            ("{{ \"}}\" }}, {{ \"\\\"}}\" }}", "}}, \"}}"),
+            ("empty: '{{ if !cond ; hello }}'", "empty: ''"),
+        ] {
+            assert_eq!(
+                render(&vars, text).expect("should never fail"),
+                expected,
+                "{text}"
+            );
+        }
+        assert_eq!(
+            render(&vars, "a\nb {{ hello"),
+            Err(TauschError::Tokenizer {
+                message: "Unterminated '{{' at column 3".to_string(),
+                span: Some(Span { start: 4, end: 6 }),
+            })
+        );
+        assert!(matches!(
+            render(&vars, "{{ missing }}"),
+            Err(TauschError::Parser(ParserError::UndefinedVariable { .. }))
+        ));
+    }
+
+    #[test]
+    fn render_error_points_into_text() {
+        let vars = vars! { "hello" => 42, "cond" => true };
+        for (text, rendered) in [
+            (
+                "é {{ nope }}",
+                "é {{ nope }}\n     ^^^^\nParsing failed: Variable 'nope' does not exist!",
+            ),
+            (
+                "{{ hello }}\nx {{ if cond : hello }}",
+                "x {{ if cond : hello }}\n             ^\nParsing failed: Expected ';' after the \
+                 condition of 'if'! Found ':' (at column 14)",
+            ),
+            (
+                "ü\n  {{ hello ? }}",
+                "  {{ hello ? }}\n           ^\nTokenizing failed: Unknown token: '?' at column 12",
+            ),
+        ] {
+            let err = render(&vars, text).expect_err("should never succeed");
+            assert_eq!(render_error(text, &err), rendered, "{text}");
+        }
+    }
+
+    #[test]
+    fn unexpected_token_lists_expected_tokens() {
+        let err = validate("if cond : hello").expect_err("should never succeed");
+        assert_eq!(
+            err,
+            TauschError::Parser(ParserError::UnexpectedToken {
+                expected: "Expected ';' after the condition of 'if'!".to_string(),
+                expected_tokens: vec![TokenType::IfEnd],
+                found: ":".to_string(),
+                column: 9,
+                span: Span { start: 8, end: 9 },
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            "Parsing failed: Expected ';' after the condition of 'if'! Found ':' (at column 9)"
+        );
+
+        let Err(TauschError::Parser(ParserError::UnexpectedToken {
+            expected_tokens,
+            found,
+            ..
+        })) = validate("if \"a\" < b ; c")
+        else {
+            panic!("expected an unexpected token");
+        };
+        assert_eq!(found, "<");
+        assert_eq!(
+            expected_tokens,
+            [TokenType::Eq, TokenType::NotEq, TokenType::In]
+        );
+    }
+
+    #[test]
+    fn tokenize_max_tokens() {
+        let toker = Tokenizer::new().with_max_tokens(4);
+        assert_eq!(
+            toker
+                .tokenize_borrowed("if cond ; hello")
+                .expect("should never fail")
+                .len(),
+            4
+        );
+        assert_eq!(
+            toker.tokenize_borrowed("if cond ; hello : world"),
+            Err(TauschError::Tokenizer {
+                message: "Too many tokens, at most 4 are allowed".to_string(),
+                span: Some(Span { start: 16, end: 17 }),
+            })
+        );
+
+        let vars = vars! { "cond" => true, "hello" => 42 };
+        let options = EvalOptions {
+            max_tokens: Some(3),
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            eval_with(&options, &vars, "hello hello hello"),
+            Ok(VariableValue::Str("424242".to_string()))
+        );
+        assert!(matches!(
+            eval_with(&options, &vars, "if cond ; hello"),
+            Err(TauschError::Tokenizer { .. })
+        ));
+    }
+
+    #[test]
+    fn eval_unless() {
+        let vars = vars! { "cond" => true, "ncond" => false, "hello" => 42, "world" => 69 };
+
+        for (input, expected) in [
+            ("unless ncond ; hello : world", VariableValue::Int(42)),
+            ("unless cond ; hello : world", VariableValue::Int(69)),
+            ("unless !cond ; hello : world", VariableValue::Int(42)),
+            ("unless cond ; hello", VariableValue::Empty),
+            (
+                "if ncond ; hello : unless cond ; hello : world",
+                VariableValue::Int(69),
+            ),
+            (
+                "unless cond && ncond ; hello : world",
+                VariableValue::Int(42),
+            ),
+        ] {
+            assert_eq!(
+                eval(&vars, input).expect("should never fail"),
+                expected,
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn template_render_many() {
+        let template = Template::compile("if cond ; hello : world").expect("should never fail");
+
+        assert_eq!(
+            template.render(&vars! { "cond" => true, "hello" => 42, "world" => 69 }),
+            Ok(VariableValue::Int(42))
+        );
+        assert_eq!(
+            template.render(&vars! { "cond" => false, "hello" => 42, "world" => "w" }),
+            Ok(VariableValue::Str("w".to_string()))
+        );
+        assert_eq!(
+            template.render(&vars! { "cond" => false }),
+            Err(TauschError::Parser(ParserError::UndefinedVariable {
+                name: "world".to_string(),
+                span: Some(Span { start: 18, end: 23 }),
+            }))
+        );
+        assert!(Template::compile("if cond").is_err());
+    }
+
+    #[test]
+    fn missing_if_end_reports_found_token() {
+        let err = validate("if cond hello : world").expect_err("should never succeed");
+        assert_eq!(
+            err,
+            TauschError::Parser(ParserError::UnexpectedToken {
+                expected: "Expected ';' after the condition of 'if'!".to_string(),
+                expected_tokens: vec![TokenType::IfEnd],
+                found: "hello".to_string(),
+                column: 9,
+                span: Span { start: 8, end: 13 },
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            "Parsing failed: Expected ';' after the condition of 'if'! Found 'hello' (at column 9)"
+        );
+
+        let err = validate("if cond && ncond").expect_err("should never succeed");
+        assert!(matches!(
+            &err,
+            TauschError::Parser(ParserError::UnexpectedEof { expected_tokens, .. })
+                if expected_tokens == &[TokenType::IfEnd]
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Parsing failed: Expected ';' after the condition of 'if'! Found end of input"
+        );
+    }
+
+    #[test]
+    fn parse_nested_else_if_groups_quickly() {
+        let mut branch = "y".to_string();
+        for _ in 0..40 {
+            branch = format!("(if c ; x : {branch}) == d ; y");
+        }
+        let input = format!("if c ; x : {branch}");
+        let options = ParseOptions {
+            max_depth: 1024,
+            ..ParseOptions::default()
+        };
+
+        let ast = parse_with(tokenize(&input).expect("should never fail"), &options)
+            .expect("should never fail");
+        let vars = vars! { "c" => false, "d" => 2, "x" => 1, "y" => 2 };
+        assert_eq!(ast.eval(&vars), Ok(VariableValue::Int(2)));
+    }
+
+    #[test]
+    fn parse_long_chains_too_deep() {
+        for (input, op) in [
+            (format!("n{}", " + n".repeat(20_000)), "+"),
+            (format!("n{}", " * n".repeat(20_000)), "*"),
+            (format!("x{}", " ?? x".repeat(20_000)), "??"),
+            (format!("if c{} ; x", " && c".repeat(20_000)), "&&"),
+            (format!("if c{} ; x", " || c".repeat(20_000)), "||"),
+            (format!("x{}", " | trim".repeat(20_000)), "|"),
+            (format!("x{}", "[0]".repeat(20_000)), "["),
+            (format!("if n{} < 1 ; x", " - n".repeat(20_000)), "-"),
+        ] {
+            let err = validate(&input).expect_err("should never succeed");
+            assert!(
+                matches!(
+                    &err,
+                    TauschError::Parser(ParserError::NestingTooDeep { max_depth: 128, span, .. })
+                        if &input[span.start..span.end] == op
+                ),
+                "{op}: {err}"
+            );
+        }
+
+        let vars = vars! { "n" => 1 };
+        let input = format!("n{}", " + n".repeat(100));
+        assert_eq!(eval(&vars, &input), Ok(VariableValue::Int(101)));
+    }
+
+    #[test]
+    fn eval_eq_floats_ieee() {
+        let vars = vars! { "z" => -0.0, "nan" => f64::NAN, "one" => 1.0 };
+
+        for (input, expected) in [
+            ("if z == 0.0 ; 1 : 0", 1),
+            ("if z != 0.0 ; 1 : 0", 0),
+            ("if nan == nan ; 1 : 0", 0),
+            ("if nan != nan ; 1 : 0", 1),
+            ("if one == 1.0 ; 1 : 0", 1),
+            ("if one == 1 ; 1 : 0", 1),
+            ("if 1 == one && one <= 1 && one >= 1 ; 1 : 0", 1),
+            ("if one != 2 ; 1 : 0", 1),
+            ("if nan == 0 ; 1 : 0", 0),
+        ] {
+            assert_eq!(
+                eval(&vars, input),
+                Ok(VariableValue::Int(expected)),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn eval_group_operands() {
+        let vars = vars! { "c" => true, "n" => 3, "x" => "abc" };
+
+        for (input, expected) in [
+            ("(if c ; 1 : 2) + 1", VariableValue::Int(2)),
+            ("(n + 1) * 2", VariableValue::Int(8)),
+            ("n * (n + 1)", VariableValue::Int(12)),
+            ("n - (n - 1)", VariableValue::Int(1)),
+            ("(x)[0]", VariableValue::Str("a".to_string())),
+            ("(x ?? n)[-1] | upper", VariableValue::Str("C".to_string())),
+            ("x[(n - 2)]", VariableValue::Str("b".to_string())),
+            ("if c ; (n + 1) * 2 : 0", VariableValue::Int(8)),
+            ("if (n + 1) * 2 > 3 ; 1 : 0", VariableValue::Int(1)),
+            ("if 3 < n * (n + 1) ; 1 : 0", VariableValue::Int(1)),
+            ("if 8 == (n + 1) * 2 ; 1 : 0", VariableValue::Int(1)),
+            ("if (n) + 1 == 4 ; 1 : 0", VariableValue::Int(1)),
+        ] {
+            assert_eq!(eval(&vars, input), Ok(expected), "{input}");
+        }
+        assert!(validate("if (n) + 1 ; x").is_err());
+    }
+
+    #[test]
+    fn options_for_templates_and_statements() {
+        let vars = vars! { "n" => 2, "s" => "a" };
+        let options = EvalOptions {
+            functions: FunctionRegistry::new().with_function("twice", |args| {
+                Ok(VariableValue::Str(args[0].to_string().repeat(2)))
+            }),
+            ..EvalOptions::default()
+        };
+
+        let template = Template::compile_with(
+            "twice(n) + missing",
+            &ParseOptions {
+                max_depth: 4,
+                ..ParseOptions::default()
+            },
+        )
+        .expect("should never fail");
+        assert!(matches!(
+            template.render(&vars),
+            Err(TauschError::Parser(ParserError::UnknownFunction { name })) if name == "twice"
+        ));
+        assert_eq!(
+            Template::compile("twice(s)")
+                .expect("should never fail")
+                .render_with(&vars, &options),
+            Ok(VariableValue::Str("aa".to_string()))
+        );
+        assert!(matches!(
+            Template::compile("n + missing + missing").expect("should never fail").render(&vars),
+            Err(TauschError::Parser(ParserError::UndefinedVariable { span, .. }))
+                if span == Some(Span { start: 4, end: 11 })
+        ));
+        assert!(
+            Template::compile_with(
+                "(((n)))",
+                &ParseOptions {
+                    max_depth: 2,
+                    ..ParseOptions::default()
+                }
+            )
+            .is_err()
+        );
+
+        assert_eq!(
+            render_with(&options, &vars, "<{{ twice(s) }}>"),
+            Ok("<aa>".to_string())
+        );
+        assert_eq!(
+            eval_all_with(&options, &vars, "twice(n)\ntwice(s)"),
+            Ok(vec![
+                VariableValue::Str("22".to_string()),
+                VariableValue::Str("aa".to_string())
+            ])
+        );
+        assert!(matches!(
+            eval_lines_with(&options, &vars, "n\nnope(n)").nth(1),
+            Some(Err(TauschError::Parser(ParserError::InStatement {
+                index: 1,
+                ..
+            })))
+        ));
+    }
+
+    #[test]
+    fn render_error_tokenizer_caret() {
+        let input = "if cond ? hello";
+        let err = tokenize(input).expect_err("should never succeed");
+        assert_eq!(
+            render_error(input, &err),
+            "if cond ? hello\n        ^\nTokenizing failed: Unknown token: '?' at column 9"
+        );
+
+        let input = "a ??\nb \"hello";
+        let err = tokenize(input).expect_err("should never succeed");
+        assert_eq!(
+            render_error(input, &err),
+            "b \"hello\n  ^\nTokenizing failed: Unterminated string literal starting at column 3"
+        );
+
+        let input = "x 123abc";
+        assert_eq!(
+            render_error(input, &tokenize(input).expect_err("should never succeed")),
+            "x 123abc\n  ^^^^^^\nTokenizing failed: Invalid number literal: '123abc' at column 3"
+        );
+    }
+
+    #[test]
+    fn eval_literal_operand_type_mismatch() {
+        let vars = vars! { "a" => "abc", "true" => 1 };
+        for (input, expected, found) in [
+            ("true + 1", "number", "bool"),
+            ("\"abc\" * 2", "number", "str"),
+            ("(a | upper) - 1", "number", "str"),
+            ("(a | len)[0]", "str", "int"),
+            ("a[(\"0\")]", "int", "str"),
+            ("if \"b\" in 1 ; a", "str", "int"),
+        ] {
+            let err = eval(&vars, input).expect_err("should never succeed");
+            assert_eq!(
+                err,
+                TauschError::Parser(ParserError::OperandTypeMismatch { expected, found }),
+                "{input}"
+            );
+            assert_eq!(
+                render_error(input, &err),
+                format!("Parsing failed: Expected a value of type {expected}, found {found}!")
+            );
+        }
+        assert_eq!(
+            eval(&vars, "a + 1"),
+            Err(TauschError::Parser(ParserError::TypeMismatch {
+                name: "a".to_string(),
+                expected: "number",
+                found: "str",
+                span: Some(Span { start: 0, end: 1 }),
+            }))
+        );
+    }
+
+    #[test]
+    fn index_operand_errors() {
+        for (input, found) in [("\"x\"[true]", "true"), ("x[1 + \"a\"]", "a")] {
+            let err = validate(input).expect_err("should never succeed");
+            assert!(
+                matches!(
+                    &err,
+                    TauschError::Parser(ParserError::UnexpectedToken { found: tok, .. }) if tok == found
+                ),
+                "{input}"
+            );
+            assert!(
+                err.to_string()
+                    .contains("Expected variable name or number as the index inside of '['!"),
+                "{err}"
+            );
+        }
+        assert_eq!(
+            validate("x[n -"),
+            Err(TauschError::Parser(ParserError::UnexpectedEof {
+                expected: "Expected variable name or number as the index inside of '['!"
+                    .to_string(),
+                expected_tokens: vec![
+                    TokenType::ParenOpen,
+                    TokenType::Variable,
+                    TokenType::IntLiteral,
+                    TokenType::FloatLiteral,
+                ],
+            }))
         );
     }
 }
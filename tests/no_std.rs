@@ -0,0 +1,20 @@
+//! Uses the crate from a `no_std` crate, run with `cargo test --no-default-features`.
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, string::ToString};
+
+use tausch::{VariableValue, eval};
+
+#[test]
+fn eval_without_std() {
+    let mut vars = BTreeMap::new();
+    vars.insert("hello".to_string(), VariableValue::Int(42));
+    vars.insert("cond".to_string(), VariableValue::Bool(true));
+
+    assert_eq!(
+        eval(&vars, "if cond ; hello + 1 : 0").expect("should never fail"),
+        VariableValue::Int(43)
+    );
+}